@@ -1,18 +1,115 @@
-use zbus::interface;
+use zbus::{
+    Connection,
+    fdo::{self, ConnectionCredentials, DBusProxy},
+    interface,
+    message::Header,
+    object_server::SignalEmitter,
+};
 
 pub trait GreeterServiceBackend: Send + Sync {
-    fn set_wallpaper_source(&self, url: String);
-    fn set_api_poller_url(&self, url: String);
-    fn login(&self);
+    /// Decides whether the caller with the given credentials may call the service methods.
+    /// Reading properties is always allowed.
+    fn authorize(&self, credentials: &ConnectionCredentials) -> fdo::Result<()>;
+
+    fn set_wallpaper_source(&self, url: String) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn set_api_poller_url(&self, url: String) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn login(&self, wait: bool) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn set_start_time(&self, start_time: String) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn clear_start_time(&self) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn set_background_label(
+        &self,
+        text: String,
+        color: String,
+    ) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn show_message(
+        &self,
+        title: String,
+        body: String,
+        severity: String,
+        expires_in: u32,
+    ) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn dismiss_message(&self) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn toggle_login_form(&self) -> impl Future<Output = fdo::Result<()>> + Send;
+    fn reload_config(&self) -> impl Future<Output = fdo::Result<()>> + Send;
+}
+
+/// Snapshot of the greeter state exposed as read-only D-Bus properties.
+/// Empty strings mean the value is not set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GreeterState {
+    /// Contest start time (RFC3339).
+    pub start_time: String,
+    /// One of `idle`, `scheduled`, `final` (last seconds) or `started`.
+    pub countdown_state: String,
+    /// One of `loading`, `ready`, `invalid` or `empty`.
+    pub wallpaper_status: String,
+    pub last_login_error: String,
+    /// Name of the session that is started on login.
+    pub session: String,
+    pub version: String,
 }
 
 pub struct GreeterService<B: 'static> {
     backend: B,
+    state: GreeterState,
 }
 
 impl<B> GreeterService<B> {
     pub fn new(backend: B) -> Self {
-        Self { backend }
+        Self {
+            backend,
+            state: GreeterState::default(),
+        }
+    }
+}
+
+impl<B: GreeterServiceBackend> GreeterService<B> {
+    /// Replaces the exposed state and emits `PropertiesChanged` for every property that changed.
+    pub async fn update_state(
+        &mut self,
+        state: GreeterState,
+        emitter: &SignalEmitter<'_>,
+    ) -> zbus::Result<()> {
+        let old = std::mem::replace(&mut self.state, state);
+        if old.start_time != self.state.start_time {
+            self.start_time_changed(emitter).await?;
+        }
+        if old.countdown_state != self.state.countdown_state {
+            self.countdown_state_changed(emitter).await?;
+        }
+        if old.wallpaper_status != self.state.wallpaper_status {
+            self.wallpaper_status_changed(emitter).await?;
+        }
+        if old.last_login_error != self.state.last_login_error {
+            self.last_login_error_changed(emitter).await?;
+        }
+        if old.session != self.state.session {
+            self.session_changed(emitter).await?;
+        }
+        if old.version != self.state.version {
+            self.version_changed(emitter).await?;
+        }
+        Ok(())
+    }
+
+    async fn check_caller(&self, connection: &Connection, header: &Header<'_>) -> fdo::Result<()> {
+        if !connection.is_bus() {
+            // peer-to-peer connection, ask the socket who is on the other side
+            let credentials = connection
+                .peer_creds()
+                .await
+                .map_err(|e| fdo::Error::AccessDenied(format!("caller is unknown: {e}")))?;
+            return self.backend.authorize(credentials);
+        }
+
+        let sender = header
+            .sender()
+            .ok_or_else(|| fdo::Error::AccessDenied("caller is unknown".to_string()))?;
+        let credentials = DBusProxy::new(connection)
+            .await?
+            .get_connection_credentials(sender.clone().into())
+            .await?;
+        self.backend.authorize(&credentials)
     }
 }
 
@@ -28,17 +125,162 @@ impl<B> GreeterService<B> {
 impl<B: GreeterServiceBackend> GreeterService<B> {
     /// Sets the source of the wallpaper to use. Can be an http url
     /// (if the machine has internet) or a local filepath.
-    async fn set_wallpaper_source(&self, url: String) {
-        self.backend.set_wallpaper_source(url);
+    async fn set_wallpaper_source(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        url: String,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.set_wallpaper_source(url).await
     }
 
-    async fn set_api_poller_url(&self, url: String) {
-        self.backend.set_api_poller_url(url);
+    async fn set_api_poller_url(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        url: String,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.set_api_poller_url(url).await
     }
 
     /// Unlocks the machine and starts the default session.
     /// This only works when a username and password have been configured for the greeter.
-    async fn login(&self) {
-        self.backend.login();
+    async fn login(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.login(false).await
+    }
+
+    /// Like `Login`, but only replies once greetd accepted or rejected the login.
+    async fn login_and_wait(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.login(true).await
+    }
+
+    /// Sets the contest start time (RFC3339). The next poll of the contest API overrides it.
+    async fn set_start_time(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        start_time: String,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.set_start_time(start_time).await
+    }
+
+    /// Clears the start time and stops the countdown.
+    async fn clear_start_time(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.clear_start_time().await
+    }
+
+    /// Sets the label shown over the background. An empty text removes the label,
+    /// an empty color falls back to white.
+    async fn set_background_label(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        text: String,
+        color: String,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.set_background_label(text, color).await
+    }
+
+    /// Shows a message overlay with the given title and body. The severity is one of
+    /// `info`, `warning` or `critical` (empty means `info`). The message is dismissed
+    /// after `expires_in` seconds, or stays until dismissed when it is 0.
+    async fn show_message(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        title: String,
+        body: String,
+        severity: String,
+        expires_in: u32,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend
+            .show_message(title, body, severity, expires_in)
+            .await
+    }
+
+    /// Dismisses the message overlay.
+    async fn dismiss_message(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.dismiss_message().await
+    }
+
+    /// Shows or hides the login form.
+    async fn toggle_login_form(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.toggle_login_form().await
+    }
+
+    /// Reloads the config files from disk.
+    async fn reload_config(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        self.check_caller(connection, &header).await?;
+        self.backend.reload_config().await
+    }
+
+    /// Contest start time (RFC3339), empty when no start time is set.
+    #[zbus(property)]
+    async fn start_time(&self) -> String {
+        self.state.start_time.clone()
+    }
+
+    /// State of the countdown: `idle`, `scheduled`, `final` or `started`.
+    #[zbus(property)]
+    async fn countdown_state(&self) -> String {
+        self.state.countdown_state.clone()
+    }
+
+    /// State of the wallpaper: `loading`, `ready`, `invalid` or `empty`.
+    #[zbus(property)]
+    async fn wallpaper_status(&self) -> String {
+        self.state.wallpaper_status.clone()
+    }
+
+    /// Error of the last failed login, empty when there was none.
+    #[zbus(property)]
+    async fn last_login_error(&self) -> String {
+        self.state.last_login_error.clone()
+    }
+
+    /// Name of the session that is started on login.
+    #[zbus(property)]
+    async fn session(&self) -> String {
+        self.state.session.clone()
+    }
+
+    /// Version of the greeter.
+    #[zbus(property)]
+    async fn version(&self) -> String {
+        self.state.version.clone()
     }
 }
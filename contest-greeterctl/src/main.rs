@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use contest_greeter_dbus::GreeterServiceProxy;
+use serde_json::{Value, json};
+use tokio::net::UnixStream;
+use zbus::{Connection, conn::Builder};
+
+/// Control a running contest-greeter over D-Bus.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Print the result as JSON.
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Connect over the session bus instead of the system bus.
+    #[arg(long, global = true)]
+    session: bool,
+
+    /// Connect directly to a greeter listening on this socket (peer-to-peer mode).
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "session")]
+    socket: Option<PathBuf>,
+
+    /// Well-known name of the greeter service.
+    #[arg(
+        long,
+        global = true,
+        default_value = "nl.luukblankenstijn.ContestGreeterService"
+    )]
+    name: String,
+
+    /// Object path of the greeter service.
+    #[arg(
+        long,
+        global = true,
+        default_value = "/nl/luukblankenstijn/ContestGreeterService"
+    )]
+    path: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// Log in with the configured credentials.
+    Login {
+        /// Wait until greetd accepted or rejected the login.
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Set the wallpaper source, an http url or a local file path.
+    SetWallpaper { source: String },
+    /// Set the contest API url.
+    SetApiUrl { url: String },
+    /// Set the contest start time (RFC3339).
+    SetStartTime { start_time: String },
+    /// Clear the contest start time.
+    ClearStartTime,
+    /// Set the background label. An empty text removes it.
+    SetLabel {
+        text: String,
+        /// Hex code of the label color.
+        #[arg(long, default_value = "")]
+        color: String,
+    },
+    /// Show a message overlay, or dismiss it with `--dismiss`.
+    Message {
+        #[arg(required_unless_present = "dismiss")]
+        title: Option<String>,
+        #[arg(default_value = "")]
+        body: String,
+        /// One of info, warning or critical.
+        #[arg(long, default_value = "info")]
+        severity: String,
+        /// Dismiss the message after this many seconds.
+        #[arg(long, default_value_t = 0)]
+        expires_in: u32,
+        #[arg(long, conflicts_with = "title")]
+        dismiss: bool,
+    },
+    /// Show or hide the login form.
+    ToggleForm,
+    /// Reload the greeter config.
+    Reload,
+    /// Show the greeter state.
+    Status,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cli = Cli::parse();
+
+    match run(&cli).await {
+        Ok(output) => print_output(cli.json, output),
+        Err(e) => {
+            if cli.json {
+                println!("{}", json!({ "ok": false, "error": format!("{e:#}") }));
+            } else {
+                eprintln!("error: {e:#}");
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> Result<Option<Value>> {
+    let connection = match &cli.socket {
+        Some(socket) => {
+            let stream = UnixStream::connect(socket)
+                .await
+                .context(format!("connecting to {}", socket.display()))?;
+            Builder::unix_stream(stream).p2p().build().await?
+        }
+        None if cli.session => Connection::session().await?,
+        None => Connection::system().await?,
+    };
+    let proxy = GreeterServiceProxy::builder(&connection)
+        .destination(cli.name.as_str())?
+        .path(cli.path.as_str())?
+        .build()
+        .await?;
+
+    match cli.command.clone() {
+        Command::Login { wait: false } => proxy.login().await?,
+        Command::Login { wait: true } => proxy.login_and_wait().await?,
+        Command::SetWallpaper { source } => proxy.set_wallpaper_source(source).await?,
+        Command::SetApiUrl { url } => proxy.set_api_poller_url(url).await?,
+        Command::SetStartTime { start_time } => proxy.set_start_time(start_time).await?,
+        Command::ClearStartTime => proxy.clear_start_time().await?,
+        Command::SetLabel { text, color } => proxy.set_background_label(text, color).await?,
+        Command::Message {
+            title: Some(title),
+            body,
+            severity,
+            expires_in,
+            ..
+        } => {
+            proxy
+                .show_message(title, body, severity, expires_in)
+                .await?
+        }
+        Command::Message { .. } => proxy.dismiss_message().await?,
+        Command::ToggleForm => proxy.toggle_login_form().await?,
+        Command::Reload => proxy.reload_config().await?,
+        Command::Status => {
+            return Ok(Some(json!({
+                "version": proxy.version().await?,
+                "start_time": proxy.start_time().await?,
+                "countdown_state": proxy.countdown_state().await?,
+                "wallpaper_status": proxy.wallpaper_status().await?,
+                "last_login_error": proxy.last_login_error().await?,
+                "session": proxy.session().await?,
+            })));
+        }
+    }
+    Ok(None)
+}
+
+fn print_output(json: bool, output: Option<Value>) {
+    match (json, output) {
+        (true, Some(output)) => println!("{output}"),
+        (true, None) => println!("{}", json!({ "ok": true })),
+        (false, Some(Value::Object(fields))) => {
+            for (key, value) in fields {
+                println!("{key}: {}", value.as_str().unwrap_or_default());
+            }
+        }
+        (false, _) => {}
+    }
+}
@@ -21,6 +21,7 @@
         {
           default = pkgs.callPackage ./nix/package.nix { };
           contest-greeter = self.packages.${system}.default;
+          contest-greeterctl = pkgs.callPackage ./nix/greeterctl.nix { };
         }
       );
 
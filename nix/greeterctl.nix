@@ -0,0 +1,23 @@
+{
+  lib,
+  rustPlatform,
+}:
+
+rustPlatform.buildRustPackage {
+  pname = "contest-greeterctl";
+  version = "0.1.0";
+
+  src = lib.cleanSource ./..;
+  sourceRoot = "source/contest-greeterctl";
+
+  cargoLock = {
+    lockFile = ./../contest-greeterctl/Cargo.lock;
+  };
+
+  meta = with lib; {
+    description = "Command-line client to control contest-greeter over D-Bus";
+    license = licenses.mit;
+    platforms = platforms.linux;
+    mainProgram = "contest-greeterctl";
+  };
+}
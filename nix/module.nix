@@ -20,14 +20,28 @@ let
   greeterConfig = {
     log_level = cfg.logLevel;
     enable_dbus = cfg.enableDbus;
+    dbus_allowed_users = cfg.dbusAllowedUsers;
+    dbus_allowed_groups = cfg.dbusAllowedGroups;
     chain = cfg.chain;
+    chains = if cfg.chains == { } then null else cfg.chains;
+    chain_timeout_ms = cfg.chainTimeoutMs;
+    form_timeout_secs = cfg.formTimeoutSecs;
+    team_id = cfg.teamId;
     background_source = cfg.backgroundSource;
     background_label = cfg.backgroundLabel;
     background_label_color = cfg.backgroundLabelColor;
     session = cfg.session;
     username = cfg.username;
     password = cfg.password;
+    form_username = cfg.formUsername;
+    form_username_locked = cfg.formUsernameLocked;
+    form_usernames = cfg.formUsernames;
+    keyboard_layouts = cfg.keyboardLayouts;
+    login_max_attempts = cfg.loginMaxAttempts;
+    login_delay_secs = cfg.loginDelaySecs;
+    login_lockout_secs = cfg.loginLockoutSecs;
     url = cfg.url;
+    hosts = if cfg.hosts == { } then null else cfg.hosts;
   };
 
   # Filter out null values
@@ -36,6 +50,15 @@ let
   configFile = tomlFormat.generate "contest-greeter.toml" filteredConfig;
 
   greeterPackage = flake.packages.${pkgs.system}.default;
+  greeterctlPackage = flake.packages.${pkgs.system}.contest-greeterctl;
+
+  serviceName = "nl.luukblankenstijn.ContestGreeterService";
+  dbusOpen = cfg.dbusAllowedUsers == [ ] && cfg.dbusAllowedGroups == [ ];
+  allowCalls = ''<allow send_destination="${serviceName}"/>'';
+
+  # cage reads the layout of the greeter itself from the environment
+  cageEnv = lib.optionalString (cfg.keyboardLayouts != [ ])
+    "${pkgs.coreutils}/bin/env XKB_DEFAULT_LAYOUT=${lib.head cfg.keyboardLayouts} ";
 in
 {
   options.services.greetd.contest-greeter = {
@@ -47,6 +70,12 @@ in
       description = "The contest-greeter package to use.";
     };
 
+    ctlPackage = mkOption {
+      type = types.package;
+      default = greeterctlPackage;
+      description = "The contest-greeterctl package to install when D-Bus is enabled.";
+    };
+
     cagePackage = mkOption {
       type = types.package;
       default = pkgs.cage;
@@ -65,10 +94,62 @@ in
       description = "Enable or disable the dbus module.";
     };
 
+    dbusAllowedUsers = mkOption {
+      type = types.listOf types.str;
+      default = [ ];
+      description = "Users (names or uids) allowed to call D-Bus methods. Root is always allowed. When this and dbusAllowedGroups are empty, every user is allowed.";
+      example = [ "fleet-agent" ];
+    };
+
+    dbusAllowedGroups = mkOption {
+      type = types.listOf types.str;
+      default = [ ];
+      description = "Groups (names or gids) whose members are allowed to call D-Bus methods.";
+      example = [ "wheel" ];
+    };
+
     chain = mkOption {
       type = types.str;
       default = "chain";
-      description = "Key sequence to toggle the login UI.";
+      description = "Key sequence to toggle the login UI, as whitespace separated key combos. Use the output of `contest-greeter --hash-chain <chain>` to keep the chain out of the Nix store.";
+      example = "ctrl+alt+F12 a d m i n";
+    };
+
+    chains = mkOption {
+      type = types.attrsOf (
+        types.enum [
+          "toggle_form"
+          "auto_login"
+          "diagnostics"
+          "reload_config"
+          "refetch_wallpaper"
+        ]
+      );
+      default = { };
+      description = "Additional key chains mapped to the action they trigger.";
+      example = {
+        "ctrl+alt+F11 l o g i n" = "auto_login";
+        "ctrl+alt+F10 d i a g" = "diagnostics";
+      };
+    };
+
+    chainTimeoutMs = mkOption {
+      type = types.ints.unsigned;
+      default = 2000;
+      description = "Maximum time between two keys of the chain in milliseconds. 0 disables the timeout.";
+    };
+
+    formTimeoutSecs = mkOption {
+      type = types.ints.unsigned;
+      default = 60;
+      description = "Seconds without input after which the login form is hidden and cleared. 0 keeps the form open.";
+    };
+
+    teamId = mkOption {
+      type = types.nullOr types.str;
+      default = null;
+      description = "Team identifier, available as {team_id} in backgroundSource, backgroundLabel and url.";
+      example = "42";
     };
 
     backgroundSource = mkOption {
@@ -79,7 +160,7 @@ in
         ]
       );
       default = null;
-      description = "File path or URL for the background image.";
+      description = "File path or URL for the background image. Supports {hostname}, {ip}, {mac} and {team_id}.";
       example = "/etc/greetd/background.png";
     };
 
@@ -116,12 +197,81 @@ in
       description = "Password for automatic login.";
     };
 
+    formUsername = mkOption {
+      type = types.nullOr types.str;
+      default = null;
+      description = "Username filled in the login form.";
+      example = "team";
+    };
+
+    formUsernameLocked = mkOption {
+      type = types.bool;
+      default = false;
+      description = "Make formUsername read-only.";
+    };
+
+    formUsernames = mkOption {
+      type = types.listOf types.str;
+      default = [ ];
+      description = "Usernames offered as a dropdown in the login form instead of a text field.";
+      example = [
+        "team"
+        "admin"
+      ];
+    };
+
+    keyboardLayouts = mkOption {
+      type = types.listOf types.str;
+      default = [ ];
+      description = "Keyboard layouts to choose from in the login form. The selected layout is passed to the session as XKB_DEFAULT_LAYOUT. The first one is used for typing into the greeter itself and for automatic logins.";
+      example = [
+        "us"
+        "de"
+        "nl"
+      ];
+    };
+
+    loginMaxAttempts = mkOption {
+      type = types.ints.unsigned;
+      default = 5;
+      description = "Failed logins from the form after which logins are locked for loginLockoutSecs. 0 never locks.";
+    };
+
+    loginDelaySecs = mkOption {
+      type = types.ints.unsigned;
+      default = 1;
+      description = "Delay after the first failed login from the form, doubled after every further failure, up to a day.";
+    };
+
+    loginLockoutSecs = mkOption {
+      type = types.ints.unsigned;
+      default = 300;
+      description = "Seconds logins are locked after loginMaxAttempts failures, at most a day.";
+    };
+
     url = mkOption {
       type = types.nullOr types.str;
       default = null;
       description = "Contest API URL returning JSON with start_time (RFC3339).";
       example = "https://api.example.com/contest";
     };
+
+    hosts = mkOption {
+      type = types.attrsOf (types.attrsOf types.anything);
+      default = { };
+      description = "Per-host config overrides, keyed by hostname or MAC address.";
+      example = {
+        team-01 = {
+          background_label = "team 1";
+        };
+      };
+    };
+
+    configDir = mkOption {
+      type = types.str;
+      default = "/etc/greetd/contest-greeter.d";
+      description = "Directory with *.toml drop-in files merged over the generated config.";
+    };
   };
 
   config = mkIf cfg.enable (
@@ -130,24 +280,47 @@ in
         services.greetd = {
           enable = true;
           settings.default_session = {
-            command = "${cfg.cagePackage}/bin/cage -s -- ${pkgs.systemd}/bin/systemd-cat -t contest-greeter ${cfg.package}/bin/contest-greeter ${configFile}";
+            command = "${cageEnv}${cfg.cagePackage}/bin/cage -s -- ${pkgs.systemd}/bin/systemd-cat -t contest-greeter ${cfg.package}/bin/contest-greeter --config ${configFile} --config-dir ${cfg.configDir}";
             user = "greeter";
           };
         };
       }
       # Only add D-Bus if enabled
       (mkIf cfg.enableDbus {
+        environment.systemPackages = [ cfg.ctlPackage ];
         services.dbus.packages = [
           (pkgs.writeTextDir "share/dbus-1/system.d/nl.luukblankenstijn.ContestGreeterService.conf" ''
             <!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-BUS Bus Configuration 1.0//EN"
              "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
             <busconfig>
               <policy user="greeter">
-                <allow own="nl.luukblankenstijn.ContestGreeterService"/>
+                <allow own="${serviceName}"/>
               </policy>
               <policy context="default">
-                <allow send_destination="nl.luukblankenstijn.ContestGreeterService"/>
+                ${
+                  if dbusOpen then
+                    allowCalls
+                  else
+                    ''
+                      <allow send_destination="${serviceName}" send_interface="org.freedesktop.DBus.Properties"/>
+                      <allow send_destination="${serviceName}" send_interface="org.freedesktop.DBus.Introspectable"/>
+                      <allow send_destination="${serviceName}" send_interface="org.freedesktop.DBus.Peer"/>
+                    ''
+                }
+              </policy>
+              <policy user="root">
+                ${allowCalls}
               </policy>
+              ${lib.concatMapStrings (user: ''
+                <policy user="${user}">
+                  ${allowCalls}
+                </policy>
+              '') cfg.dbusAllowedUsers}
+              ${lib.concatMapStrings (group: ''
+                <policy group="${group}">
+                  ${allowCalls}
+                </policy>
+              '') cfg.dbusAllowedGroups}
             </busconfig>
           '')
         ];
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// A greetd greeter for contests with countdown support.
+#[derive(Debug, Clone, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the TOML config file.
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Path to the TOML config file (kept for backwards compatibility).
+    #[arg(value_name = "CONFIG", conflicts_with = "config", hide = true)]
+    config_positional: Option<PathBuf>,
+
+    /// Directory with `*.toml` drop-ins merged over the config [default: `<config>.d`].
+    #[arg(long, value_name = "DIR")]
+    config_dir: Option<PathBuf>,
+
+    /// Override the log level from the config (env_logger style, e.g. `info`, `debug`).
+    #[arg(short, long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Never contact greetd, only log what would happen on login.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Run in a regular window instead of fullscreen, for development outside cage.
+    #[arg(long)]
+    pub windowed: bool,
+
+    /// Simulate a contest starting in the given number of seconds. Start times from the
+    /// contest API are ignored.
+    #[arg(long, value_name = "SECS")]
+    pub demo_start_in: Option<u64>,
+
+    /// Print a salted hash of the given key chain for use in the config, then exit.
+    #[arg(long, value_name = "CHAIN")]
+    pub hash_chain: Option<String>,
+}
+
+impl Cli {
+    pub fn config_path(&self) -> PathBuf {
+        self.config
+            .clone()
+            .or_else(|| self.config_positional.clone())
+            .unwrap_or_else(|| PathBuf::from("/etc/greetd/contest-greeter.toml"))
+    }
+
+    pub fn config_dir(&self) -> PathBuf {
+        self.config_dir
+            .clone()
+            .unwrap_or_else(|| self.config_path().with_extension("d"))
+    }
+}
@@ -1,10 +1,28 @@
-use std::fs;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
-use schemars::JsonSchema;
+use anyhow::{Context, Result, anyhow};
+use log::{debug, warn};
+use schemars::{
+    JsonSchema, Map,
+    schema::{InstanceType, Schema, SchemaObject},
+    schema_for,
+};
 use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::host;
 
 /// Top-level configuration combining UI, greeter, and contest API settings.
+///
+/// Besides these fields, every config file may contain a `[hosts.<name>]` table, where
+/// `<name>` is a hostname or MAC address. Tables matching this machine are applied last.
+///
+/// Each field can also be overridden with a `CONTEST_GREETER_<FIELD>` environment variable,
+/// e.g. `CONTEST_GREETER_URL`. Non-string fields take a TOML value, e.g. `false`.
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Default)]
 pub struct Conf {
     /// Log level (env_logger style, e.g. `info`, `debug`).
@@ -15,11 +33,56 @@ pub struct Conf {
     #[serde(default = "default_enable_dbus")]
     pub enable_dbus: bool,
 
-    /// Key sequence to toggle the login UI.
+    /// Bus to offer the D-Bus service on: `system`, `session` or `peer` (a private socket).
+    #[serde(default)]
+    pub(crate) dbus_bus: DbusBus,
+
+    /// Socket path to listen on when `dbus_bus` is `peer`.
+    pub(crate) dbus_socket_path: Option<String>,
+
+    /// Well-known name of the D-Bus service (ignored for `peer`).
+    #[serde(default = "default_dbus_name")]
+    pub(crate) dbus_name: String,
+
+    /// Object path of the D-Bus service.
+    #[serde(default = "default_dbus_path")]
+    pub(crate) dbus_path: String,
+
+    /// Users (names or uids) allowed to call D-Bus methods. Root is always allowed.
+    /// When this and `dbus_allowed_groups` are both empty, every user is allowed.
+    #[serde(default)]
+    pub(crate) dbus_allowed_users: Vec<String>,
+
+    /// Groups (names or gids) whose members are allowed to call D-Bus methods.
+    #[serde(default)]
+    pub(crate) dbus_allowed_groups: Vec<String>,
+
+    /// Key sequence to toggle the login UI: whitespace separated key combos like
+    /// `ctrl+alt+F12 a d m i n`. Modifiers are `ctrl`, `alt`, `shift` and `super`.
+    /// Use `contest-greeter --hash-chain <CHAIN>` to store a salted hash instead.
     #[serde(default = "default_chain")]
     pub(crate) chain: String,
 
-    /// File path or URL for the background image.
+    /// Additional key chains mapped to the action they trigger, e.g.
+    /// `"ctrl+alt+F11 l o g i n" = "auto_login"`.
+    #[serde(default)]
+    pub(crate) chains: BTreeMap<String, ChainAction>,
+
+    /// Maximum time between two keys of a chain in milliseconds. 0 disables the timeout.
+    #[serde(default = "default_chain_timeout_ms")]
+    pub(crate) chain_timeout_ms: u64,
+
+    /// Seconds without input after which the login form is hidden and cleared.
+    /// 0 keeps the form open.
+    #[serde(default = "default_form_timeout_secs")]
+    pub(crate) form_timeout_secs: u64,
+
+    /// Team identifier, available as `{team_id}` in `background_source`,
+    /// `background_label` and `url`. Usually set in a `[hosts.<name>]` section.
+    pub(crate) team_id: Option<String>,
+
+    /// File path or URL for the background image. Supports the `{hostname}`, `{ip}`, `{mac}`
+    /// and `{team_id}` placeholders, as do `background_label` and `url`.
     pub(crate) background_source: Option<String>,
 
     // Label to display over the background
@@ -39,10 +102,64 @@ pub struct Conf {
     #[serde(default)]
     pub(crate) password: String,
 
-    /// Contest API URL returning a JSON object with `start_time` (RFC3339).
+    /// Username filled in the login form.
+    pub(crate) form_username: Option<String>,
+
+    /// Makes `form_username` read-only.
+    #[serde(default)]
+    pub(crate) form_username_locked: bool,
+
+    /// Usernames offered as a dropdown in the login form instead of a text field.
+    #[serde(default)]
+    pub(crate) form_usernames: Vec<String>,
+
+    /// Keyboard layouts to choose from in the login form, e.g. `["us", "de", "nl"]`.
+    /// The selected layout is passed to the session as `XKB_DEFAULT_LAYOUT`. Automatic logins
+    /// use the last selected layout, or the first one. It does not change typing into the
+    /// form, which uses the layout of the compositor (the NixOS module starts cage with the
+    /// first one). The form shows that layout below the selector.
+    #[serde(default)]
+    pub(crate) keyboard_layouts: Vec<String>,
+
+    /// Failed logins from the form after which logins are locked for `login_lockout_secs`.
+    /// 0 never locks.
+    #[serde(default = "default_login_max_attempts")]
+    pub(crate) login_max_attempts: u32,
+
+    /// Delay in seconds after the first failed login from the form, doubled after every
+    /// further failure, up to a day.
+    #[serde(default = "default_login_delay_secs")]
+    pub(crate) login_delay_secs: u64,
+
+    /// Seconds logins are locked after `login_max_attempts` failures, at most a day.
+    #[serde(default = "default_login_lockout_secs")]
+    pub(crate) login_lockout_secs: u64,
+
+    /// Contest API URL returning a JSON object with `start_time` (RFC3339), and optionally a
+    /// `message` for the overlay and a list of `announcements`.
     pub(crate) url: Option<String>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DbusBus {
+    #[default]
+    System,
+    Session,
+    Peer,
+}
+
+/// Action triggered by a key chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainAction {
+    ToggleForm,
+    AutoLogin,
+    Diagnostics,
+    ReloadConfig,
+    RefetchWallpaper,
+}
+
 fn default_log_level() -> String {
     "info".into()
 }
@@ -51,23 +168,391 @@ fn default_enable_dbus() -> bool {
     true
 }
 
+fn default_dbus_name() -> String {
+    "nl.luukblankenstijn.ContestGreeterService".into()
+}
+
+fn default_dbus_path() -> String {
+    "/nl/luukblankenstijn/ContestGreeterService".into()
+}
+
 fn default_chain() -> String {
     "chain".into()
 }
 
+fn default_chain_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_form_timeout_secs() -> u64 {
+    60
+}
+
+fn default_login_max_attempts() -> u32 {
+    5
+}
+
+fn default_login_delay_secs() -> u64 {
+    1
+}
+
+fn default_login_lockout_secs() -> u64 {
+    300
+}
+
 impl Conf {
-    pub fn new_default() -> Self {
-        Self {
-            log_level: default_log_level(),
-            enable_dbus: default_enable_dbus(),
-            chain: default_chain(),
-            ..Default::default()
-        }
+    /// All key chains with their action, including `chain` which toggles the form.
+    pub(crate) fn key_chains(&self) -> Vec<(String, ChainAction)> {
+        let mut chains = vec![(self.chain.clone(), ChainAction::ToggleForm)];
+        chains.extend(
+            self.chains
+                .iter()
+                .map(|(chain, action)| (chain.clone(), *action)),
+        );
+        chains
     }
 }
 
-pub fn get_conf(path: &str) -> Result<Conf> {
-    let text = fs::read_to_string(path)?;
-    let conf: Conf = toml::from_str(&text)?;
+/// Reads the config at `path`, merges every `*.toml` file in `drop_in_dir` on top of it
+/// in lexical order, applies the `hosts` sections matching this machine and finally the
+/// `CONTEST_GREETER_*` environment variables.
+///
+/// Fails when `path` is missing or does not fit the config, so a reload keeps the current
+/// config while the file is being replaced. Drop-ins, host sections and environment
+/// variables that cannot be read or do not fit the config are skipped and logged.
+pub fn get_conf(path: &Path, drop_in_dir: &Path) -> Result<Conf> {
+    let base = read_table(path)?;
+    validate(&base).context(format!("parsing {}", path.display()))?;
+    let (conf, skipped) = apply_layers(base, drop_in_dir);
+    for error in skipped {
+        warn!("{error:#}");
+    }
     Ok(conf)
 }
+
+/// Config used at startup. Like [`get_conf`], but a missing `path` counts as an empty config
+/// and one that cannot be read or does not fit is skipped, so the greeter always starts. Returns why layers were skipped
+/// instead of logging it, as the logger is not set up yet.
+pub fn load_conf(path: &Path, drop_in_dir: &Path) -> (Conf, Vec<anyhow::Error>) {
+    let mut skipped = vec![];
+    let base = read_base(path).unwrap_or_else(|e| {
+        skipped.push(e.context(format!("skipping {}", path.display())));
+        Table::new()
+    });
+    let (conf, layers_skipped) = apply_layers(base, drop_in_dir);
+    skipped.extend(layers_skipped);
+    (conf, skipped)
+}
+
+/// Reads the main config, where a missing file counts as empty.
+fn read_base(path: &Path) -> Result<Table> {
+    let table = match fs::metadata(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Table::new(),
+        _ => read_table(path)?,
+    };
+    validate(&table).context(format!("parsing {}", path.display()))?;
+    Ok(table)
+}
+
+/// Applies the drop-ins, host sections and environment variables on top of `table`.
+/// Each layer is only applied when the result still fits the config.
+fn apply_layers(mut table: Table, drop_in_dir: &Path) -> (Conf, Vec<anyhow::Error>) {
+    let mut skipped = vec![];
+    let files = drop_in_files(drop_in_dir).unwrap_or_else(|e| {
+        skipped.push(e.context("skipping the config drop-ins"));
+        vec![]
+    });
+    for file in files {
+        debug!("merging config drop-in {}", file.display());
+        let merged = read_table(&file).and_then(|overlay| {
+            let mut merged = table.clone();
+            merge_tables(&mut merged, overlay);
+            validate(&merged)
+                .context(format!("parsing {}", file.display()))
+                .map(|_| merged)
+        });
+        match merged {
+            Ok(merged) => table = merged,
+            Err(e) => skipped.push(e.context(format!("skipping {}", file.display()))),
+        }
+    }
+
+    if let Some(Value::Table(hosts)) = table.remove("hosts") {
+        let mut keys: Vec<String> = host::hostname().into_iter().collect();
+        keys.extend(host::mac_addresses());
+        apply_host_sections(&mut table, &hosts, &keys, &mut skipped);
+    }
+    apply_overrides(&mut table, |name| env::var(name).ok(), &mut skipped);
+
+    // every layer was checked, so this only fails if the checks missed something
+    let conf = table.try_into().unwrap_or_else(|e| {
+        skipped.push(anyhow!(e).context("using the default config"));
+        Table::new()
+            .try_into()
+            .expect("an empty config only uses defaults")
+    });
+    (conf, skipped)
+}
+
+fn read_table(path: &Path) -> Result<Table> {
+    let text = fs::read_to_string(path).context(format!("reading {}", path.display()))?;
+    toml::from_str(&text).context(format!("parsing {}", path.display()))
+}
+
+/// Checks that `table` deserializes into a [`Conf`], ignoring the `hosts` sections.
+fn validate(table: &Table) -> Result<()> {
+    let mut table = table.clone();
+    table.remove("hosts");
+    table.try_into::<Conf>()?;
+    Ok(())
+}
+
+pub(crate) fn drop_in_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).context(format!("reading {}", dir.display())),
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Applies the host sections named by `keys` in order, usually the hostname followed by
+/// the MAC addresses. Sections that do not fit the config are skipped.
+fn apply_host_sections(
+    table: &mut Table,
+    hosts: &Table,
+    keys: &[String],
+    skipped: &mut Vec<anyhow::Error>,
+) {
+    for key in keys {
+        let section = hosts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, section)| section);
+        if let Some(Value::Table(section)) = section {
+            debug!("applying config section for host {key}");
+            let mut merged = table.clone();
+            merge_tables(&mut merged, section.clone());
+            match validate(&merged) {
+                Ok(()) => *table = merged,
+                Err(e) => {
+                    skipped.push(e.context(format!("skipping config section for host {key}")))
+                }
+            }
+        }
+    }
+}
+
+/// Sets each field for which `lookup` returns a value for its `CONTEST_GREETER_<FIELD>`
+/// variable. Values that do not fit the field are added to `skipped`.
+fn apply_overrides(
+    table: &mut Table,
+    lookup: impl Fn(&str) -> Option<String>,
+    skipped: &mut Vec<anyhow::Error>,
+) {
+    let root = schema_for!(Conf);
+    let Some(object) = root.schema.object else {
+        return;
+    };
+
+    for (field, schema) in object.properties {
+        let name = format!("CONTEST_GREETER_{}", field.to_uppercase());
+        let Some(raw) = lookup(&name) else {
+            continue;
+        };
+
+        let value = if accepts_string(&schema, &root.definitions) {
+            Some(Value::String(raw))
+        } else {
+            toml::from_str::<Table>(&format!("value = {raw}"))
+                .ok()
+                .and_then(|mut t| t.remove("value"))
+        };
+        let Some(value) = value else {
+            skipped.push(anyhow!("skipping {name}: not a TOML value"));
+            continue;
+        };
+        let mut overridden = table.clone();
+        overridden.insert(field.clone(), value);
+        match validate(&overridden) {
+            Ok(()) => {
+                debug!("overriding {field} from {name}");
+                *table = overridden;
+            }
+            Err(e) => skipped.push(e.context(format!("skipping {name}"))),
+        }
+    }
+}
+
+fn accepts_string(schema: &Schema, definitions: &Map<String, Schema>) -> bool {
+    match schema {
+        Schema::Object(SchemaObject {
+            instance_type: Some(instance_type),
+            ..
+        }) => instance_type.contains(&InstanceType::String),
+        Schema::Object(SchemaObject {
+            reference: Some(reference),
+            ..
+        }) => reference
+            .strip_prefix("#/definitions/")
+            .and_then(|name| definitions.get(name))
+            .is_some_and(|schema| accepts_string(schema, definitions)),
+        _ => false,
+    }
+}
+
+/// Recursively merges `overlay` into `base`, with values from `overlay` taking precedence.
+fn merge_tables(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base_table)), Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(table: &mut Table, vars: &[(&str, &str)]) -> Vec<anyhow::Error> {
+        let mut skipped = vec![];
+        apply_overrides(
+            table,
+            |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            },
+            &mut skipped,
+        );
+        skipped
+    }
+
+    #[test]
+    fn env_overrides_parse_strings_and_toml_values() {
+        let mut table = Table::new();
+        let skipped = overrides(
+            &mut table,
+            &[
+                ("CONTEST_GREETER_CHAIN", "ctrl+alt+F12 a"),
+                ("CONTEST_GREETER_SESSION", "true"),
+                ("CONTEST_GREETER_ENABLE_DBUS", "false"),
+                ("CONTEST_GREETER_CHAIN_TIMEOUT_MS", "500"),
+                ("CONTEST_GREETER_KEYBOARD_LAYOUTS", r#"["us", "de"]"#),
+                ("CONTEST_GREETER_CHAINS", r#"{ "d i a g" = "diagnostics" }"#),
+            ],
+        );
+        assert!(skipped.is_empty());
+
+        let conf: Conf = table.try_into().unwrap();
+        assert_eq!(conf.chain, "ctrl+alt+F12 a");
+        assert_eq!(conf.session.as_deref(), Some("true"));
+        assert!(!conf.enable_dbus);
+        assert_eq!(conf.chain_timeout_ms, 500);
+        assert_eq!(conf.keyboard_layouts, ["us", "de"]);
+        assert_eq!(conf.chains["d i a g"], ChainAction::Diagnostics);
+    }
+
+    #[test]
+    fn skips_invalid_env_overrides() {
+        let mut table = Table::new();
+        table.insert("chain_timeout_ms".into(), Value::Integer(500));
+        let skipped = overrides(
+            &mut table,
+            &[
+                ("CONTEST_GREETER_CHAIN_TIMEOUT_MS", "soon"),
+                ("CONTEST_GREETER_ENABLE_DBUS", "\"no\""),
+                ("CONTEST_GREETER_FORM_TIMEOUT_SECS", "30"),
+            ],
+        );
+        assert_eq!(skipped.len(), 2);
+
+        let conf: Conf = table.try_into().unwrap();
+        assert_eq!(conf.chain_timeout_ms, 500);
+        assert!(conf.enable_dbus);
+        assert_eq!(conf.form_timeout_secs, 30);
+    }
+
+    #[test]
+    fn missing_file_uses_defaults_and_bad_drop_ins_are_skipped() {
+        let dir = env::temp_dir().join(format!("contest-greeter-conf-{}", std::process::id()));
+        let drop_in_dir = dir.join("conf.d");
+        fs::create_dir_all(&drop_in_dir).unwrap();
+        fs::write(drop_in_dir.join("10-chain.toml"), "chain = \"secret\"").unwrap();
+        fs::write(drop_in_dir.join("20-broken.toml"), "chain = ").unwrap();
+        fs::write(drop_in_dir.join("30-wrong-type.toml"), "chain = 5").unwrap();
+        fs::write(drop_in_dir.join("40-timeout.toml"), "chain_timeout_ms = 10").unwrap();
+
+        let (conf, skipped) = load_conf(&dir.join("missing.toml"), &drop_in_dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(conf.chain, "secret");
+        assert_eq!(conf.chain_timeout_ms, 10);
+        assert_eq!(conf.form_timeout_secs, default_form_timeout_secs());
+        assert_eq!(skipped.len(), 2);
+    }
+
+    #[test]
+    fn skips_host_sections_that_do_not_fit() {
+        let mut table: Table = toml::from_str(
+            r#"
+            chain_timeout_ms = 10
+
+            [hosts.team-01]
+            chain_timeout_ms = "fast"
+
+            [hosts."aa:bb:cc:dd:ee:ff"]
+            team_id = "team 1"
+            "#,
+        )
+        .unwrap();
+        let Some(Value::Table(hosts)) = table.remove("hosts") else {
+            panic!("expected hosts");
+        };
+        let mut skipped = vec![];
+        apply_host_sections(
+            &mut table,
+            &hosts,
+            &["TEAM-01".to_string(), "aa:bb:cc:dd:ee:ff".to_string()],
+            &mut skipped,
+        );
+        assert_eq!(skipped.len(), 1);
+
+        let conf: Conf = table.try_into().unwrap();
+        assert_eq!(conf.chain_timeout_ms, 10);
+        assert_eq!(conf.team_id.as_deref(), Some("team 1"));
+    }
+
+    #[test]
+    fn startup_skips_a_broken_config_file() {
+        let dir = env::temp_dir().join(format!("contest-greeter-broken-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "chain_timeout_ms = \"fast\"").unwrap();
+
+        let (conf, skipped) = load_conf(&path, &dir.join("conf.d"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(conf.chain_timeout_ms, default_chain_timeout_ms());
+        assert_eq!(skipped.len(), 1);
+    }
+
+    #[test]
+    fn reload_fails_without_the_config_file() {
+        let dir = env::temp_dir().join(format!("contest-greeter-reload-{}", std::process::id()));
+
+        assert!(get_conf(&dir.join("missing.toml"), &dir.join("conf.d")).is_err());
+    }
+}
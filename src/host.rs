@@ -0,0 +1,76 @@
+use std::{ffi::CString, fs, net::IpAddr, path::Path};
+
+use local_ip_address::list_afinet_netifas;
+use nix::unistd::{Gid, Group, Uid, User, getgrouplist};
+
+/// Hostname of this machine, as reported by the kernel.
+pub fn hostname() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
+/// Lowercase MAC addresses of the physical network interfaces, sorted by interface name.
+/// Interfaces without a device, like bridges, `docker0` and `veth*`, are left out.
+pub fn mac_addresses() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/sys/class/net") else {
+        return vec![];
+    };
+
+    let mut interfaces: Vec<_> = entries
+        .flatten()
+        .filter(|entry| entry.path().join("device").exists())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    interfaces.sort();
+
+    interfaces
+        .iter()
+        .filter_map(|interface| mac_address(interface))
+        .collect()
+}
+
+/// Lowercase MAC address of the interface that has `ip`.
+pub fn mac_address_of_ip(ip: IpAddr) -> Option<String> {
+    let interfaces = list_afinet_netifas().ok()?;
+    let (interface, _) = interfaces.iter().find(|(_, address)| *address == ip)?;
+    mac_address(interface)
+}
+
+fn mac_address(interface: &str) -> Option<String> {
+    fs::read_to_string(Path::new("/sys/class/net").join(interface).join("address"))
+        .ok()
+        .map(|address| address.trim().to_lowercase())
+        .filter(|address| !address.is_empty() && address != "00:00:00:00:00:00")
+}
+
+/// Uid of the user with the given name, looked up through NSS so LDAP or systemd-homed
+/// users are found as well.
+pub fn user_id(name: &str) -> Option<u32> {
+    User::from_name(name)
+        .ok()
+        .flatten()
+        .map(|user| user.uid.as_raw())
+}
+
+/// Gid of the group with the given name, looked up through NSS.
+pub fn group_id(name: &str) -> Option<u32> {
+    Group::from_name(name)
+        .ok()
+        .flatten()
+        .map(|group| group.gid.as_raw())
+}
+
+/// Primary and supplementary group ids of the user with the given uid.
+pub fn group_ids(uid: u32) -> Vec<u32> {
+    let Ok(Some(user)) = User::from_uid(Uid::from_raw(uid)) else {
+        return vec![];
+    };
+    let Ok(name) = CString::new(user.name) else {
+        return vec![];
+    };
+    getgrouplist(&name, user.gid)
+        .map(|gids| gids.into_iter().map(Gid::as_raw).collect())
+        .unwrap_or_else(|_| vec![user.gid.as_raw()])
+}
@@ -1,2 +1,3 @@
 pub mod greeter_client;
+pub mod login_throttle;
 mod sessions;
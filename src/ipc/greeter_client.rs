@@ -1,26 +1,62 @@
 use anyhow::{Result, anyhow};
 use greetd_ipc::ErrorType;
 use iced::Task;
-use log::{debug, error, info};
-use std::{env, os::unix::net::UnixStream};
+use log::{debug, error, info, warn};
+use std::{env, os::unix::net::UnixStream, time::Instant};
 
 use greetd_ipc::{AuthMessageType, Request, Response, codec::SyncCodec};
 
-use super::sessions;
-use crate::ui::Message;
+use super::{login_throttle::LoginThrottle, sessions};
+use crate::{subscriptions::dbus::DbusReply, ui::Message};
 
 #[derive(Debug)]
 pub struct GreeterClient {
     session: Option<String>,
     username: String,
     password: String,
+    dry_run: bool,
+    resolved_session: Option<String>,
+    /// Layout for logins with the configured credentials.
+    keyboard_layout: Option<String>,
+    last_error: Option<String>,
+    /// Id of the next login attempt, used to match each result to its attempt.
+    next_attempt: u64,
+    /// Replies waiting for the result of the attempt with the given id.
+    pending_replies: Vec<(u64, DbusReply)>,
+    throttle: LoginThrottle,
+    /// Attempt id and username of the manual login that is in progress.
+    manual_login: Option<(u64, String)>,
 }
 
 pub enum GreeterClientMessage {
-    Login,
-    LoginWithCredentials(String, String),
-    LoginResult(Result<LoginResult, String>),
+    /// Logs in with the configured credentials. A `reply` is answered once the login
+    /// started, or with `wait` once greetd accepted or rejected it.
+    Login {
+        reply: Option<DbusReply>,
+        wait: bool,
+    },
+    /// Login entered in the form, subject to the login throttle. Without a session the
+    /// configured one is started.
+    ManualLogin {
+        username: String,
+        password: String,
+        session: Option<String>,
+        keyboard_layout: Option<String>,
+    },
+    LoginWithCredentials {
+        attempt: u64,
+        username: String,
+        password: String,
+        session: Option<String>,
+        keyboard_layout: Option<String>,
+    },
+    LoginResult {
+        attempt: u64,
+        result: Result<LoginResult, String>,
+    },
     LoginError(String),
+    /// Manual logins are refused until the given time.
+    LoginBlocked(Instant),
 }
 
 impl From<GreeterClientMessage> for Message {
@@ -30,56 +66,208 @@ impl From<GreeterClientMessage> for Message {
 }
 
 impl GreeterClient {
-    pub fn new(session: Option<String>, username: String, password: String) -> Self {
+    pub fn new(
+        session: Option<String>,
+        username: String,
+        password: String,
+        dry_run: bool,
+        throttle: LoginThrottle,
+    ) -> Self {
         Self {
+            resolved_session: resolve_session_name(session.as_ref()),
             session,
             username,
             password,
+            dry_run,
+            keyboard_layout: None,
+            last_error: None,
+            next_attempt: 0,
+            pending_replies: vec![],
+            throttle,
+            manual_login: None,
         }
     }
 
+    pub fn set_keyboard_layout(&mut self, keyboard_layout: Option<String>) {
+        self.keyboard_layout = keyboard_layout;
+    }
+
+    pub fn throttle_mut(&mut self) -> &mut LoginThrottle {
+        &mut self.throttle
+    }
+
+    /// Name of the session that is started on login, if one could be found.
+    pub fn resolved_session(&self) -> Option<&str> {
+        self.resolved_session.as_deref()
+    }
+
+    /// Names of all installed sessions.
+    pub fn session_names(&self) -> Vec<String> {
+        match sessions::get_sessions() {
+            Ok(sessions) => sessions.into_iter().map(|s| s.name).collect(),
+            Err(e) => {
+                error!("error getting sessions, {e}");
+                vec![]
+            }
+        }
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    pub fn reconfigure(&mut self, session: Option<String>, username: String, password: String) {
+        self.resolved_session = resolve_session_name(session.as_ref());
+        self.session = session;
+        self.username = username;
+        self.password = password;
+    }
+
+    fn next_attempt(&mut self) -> u64 {
+        self.next_attempt += 1;
+        self.next_attempt
+    }
+
     pub fn update(&mut self, msg: GreeterClientMessage) -> Task<GreeterClientMessage> {
         match msg {
-            GreeterClientMessage::Login => {
+            GreeterClientMessage::Login { reply, wait } => {
                 if self.username.is_empty() && self.password.is_empty() {
                     debug!("username and password not set, not logging in");
+                    if let Some(reply) = reply {
+                        reply.send(Err("no username and password configured".to_string()));
+                    }
                     return Task::none();
                 }
-                return Task::done(GreeterClientMessage::LoginWithCredentials(
-                    self.username.clone(),
-                    self.password.clone(),
-                ));
+                let attempt = self.next_attempt();
+                match reply {
+                    Some(reply) if wait => self.pending_replies.push((attempt, reply)),
+                    Some(reply) => reply.send(Ok(())),
+                    None => {}
+                }
+                return Task::done(GreeterClientMessage::LoginWithCredentials {
+                    attempt,
+                    username: self.username.clone(),
+                    password: self.password.clone(),
+                    session: None,
+                    keyboard_layout: self.keyboard_layout.clone(),
+                });
+            }
+            GreeterClientMessage::ManualLogin {
+                username,
+                password,
+                session,
+                keyboard_layout,
+            } => {
+                if let Some(until) = self.throttle.blocked_until(Instant::now()) {
+                    warn!(
+                        "refused login attempt for {username}, logins are blocked for {}s",
+                        until.saturating_duration_since(Instant::now()).as_secs()
+                    );
+                    return Task::done(GreeterClientMessage::LoginBlocked(until));
+                }
+                info!("manual login attempt for {username}");
+                let attempt = self.next_attempt();
+                self.manual_login = Some((attempt, username.clone()));
+                return Task::done(GreeterClientMessage::LoginWithCredentials {
+                    attempt,
+                    username,
+                    password,
+                    session,
+                    keyboard_layout,
+                });
             }
-            GreeterClientMessage::LoginWithCredentials(username, password) => {
-                let session = self.session.clone();
+            GreeterClientMessage::LoginWithCredentials {
+                attempt,
+                username,
+                password,
+                session,
+                keyboard_layout,
+            } => {
+                let session = session.or_else(|| self.session.clone());
+                let dry_run = self.dry_run;
                 info!("staring login");
                 return Task::perform(
                     async move {
                         tokio::task::spawn_blocking(move || {
-                            login(username, password, session).map_err(|e| e.to_string())
+                            if dry_run {
+                                dry_run_login(username, session, keyboard_layout)
+                                    .map_err(|e| e.to_string())
+                            } else {
+                                login(username, password, session, keyboard_layout)
+                                    .map_err(|e| e.to_string())
+                            }
                         })
                         .await
                         .unwrap_or_else(|_| Err("Task panicked".to_string()))
                     },
-                    GreeterClientMessage::LoginResult,
+                    move |result| GreeterClientMessage::LoginResult { attempt, result },
                 );
             }
-            GreeterClientMessage::LoginResult(result) => match result {
-                Ok(result) => {
-                    if let LoginResult::Failure(msg) = result {
-                        return Task::done(GreeterClientMessage::LoginError(msg));
+            GreeterClientMessage::LoginResult { attempt, result } => {
+                let outcome = match &result {
+                    Ok(LoginResult::Success) => Ok(()),
+                    Ok(LoginResult::Failure(msg)) => Err(msg.clone()),
+                    Ok(LoginResult::DryRun) => Err(DRY_RUN_MESSAGE.to_string()),
+                    Err(e) => Err(e.clone()),
+                };
+                let (replies, pending) = std::mem::take(&mut self.pending_replies)
+                    .into_iter()
+                    .partition(|(id, _)| *id == attempt);
+                self.pending_replies = pending;
+                for (_, reply) in replies {
+                    reply.send(outcome.clone());
+                }
+
+                let mut blocked = Task::none();
+                if let Some((_, username)) = self.manual_login.take_if(|(id, _)| *id == attempt) {
+                    if let Ok(LoginResult::DryRun) = result {
+                        info!("dry run login for {username}, not counted as a failure");
+                    } else if outcome.is_ok() {
+                        info!("manual login for {username} succeeded");
+                        self.throttle.record_success();
                     } else {
-                        return iced::exit();
+                        let failures = self.throttle.failures() + 1;
+                        let now = Instant::now();
+                        if self.throttle.record_failure(now) {
+                            warn!(
+                                "manual login for {username} failed {failures} times, locking logins"
+                            );
+                        } else {
+                            warn!("manual login for {username} failed (attempt {failures})");
+                        }
+                        if let Some(until) = self.throttle.blocked_until(now) {
+                            blocked = Task::done(GreeterClientMessage::LoginBlocked(until));
+                        }
                     }
                 }
-                Err(e) => {
-                    error!("Error when login in: {e}");
-                    return Task::done(GreeterClientMessage::LoginError(
-                        "Unexpected error".to_string(),
-                    ));
+
+                match result {
+                    Ok(LoginResult::Success) => return iced::exit(),
+                    Ok(LoginResult::Failure(msg)) => {
+                        self.last_error = Some(msg.clone());
+                        return Task::batch(vec![
+                            Task::done(GreeterClientMessage::LoginError(msg)),
+                            blocked,
+                        ]);
+                    }
+                    Ok(LoginResult::DryRun) => {
+                        return Task::done(GreeterClientMessage::LoginError(
+                            DRY_RUN_MESSAGE.to_string(),
+                        ));
+                    }
+                    Err(e) => {
+                        error!("Error when login in: {e}");
+                        self.last_error = Some(e);
+                        return Task::batch(vec![
+                            Task::done(GreeterClientMessage::LoginError(
+                                "Unexpected error".to_string(),
+                            )),
+                            blocked,
+                        ]);
+                    }
                 }
-            },
-            GreeterClientMessage::LoginError(_) => {
+            }
+            GreeterClientMessage::LoginError(_) | GreeterClientMessage::LoginBlocked(_) => {
                 // handled by parent
             }
         }
@@ -91,9 +279,55 @@ impl GreeterClient {
 pub enum LoginResult {
     Success,
     Failure(String),
+    /// `--dry-run` only logged what it would have done.
+    DryRun,
 }
 
-fn login(username: String, password: String, session: Option<String>) -> Result<LoginResult> {
+const DRY_RUN_MESSAGE: &str = "dry run, not logging in";
+
+fn find_session(session: Option<&String>) -> Result<sessions::Session> {
+    let sessions = match sessions::get_sessions() {
+        Ok(session) => session,
+        Err(e) => return Err(anyhow!("error getting sessions, {e}")),
+    };
+    let session = if let Some(session_name) = session {
+        sessions.into_iter().find(|s| &s.name == session_name)
+    } else {
+        sessions.into_iter().next()
+    };
+    session.ok_or_else(|| anyhow!("no sessoins available"))
+}
+
+fn resolve_session_name(session: Option<&String>) -> Option<String> {
+    match find_session(session) {
+        Ok(session) => Some(session.name),
+        Err(e) => {
+            error!("failed to resolve session: {e}");
+            None
+        }
+    }
+}
+
+fn dry_run_login(
+    username: String,
+    session: Option<String>,
+    keyboard_layout: Option<String>,
+) -> Result<LoginResult> {
+    let session = find_session(session.as_ref())?;
+    let (cmd, env) = session.get_session_command(keyboard_layout.as_deref());
+    info!(
+        "dry run: would log in as {username} and start session {} with command {cmd:?} and env {env:?}",
+        session.name
+    );
+    Ok(LoginResult::DryRun)
+}
+
+fn login(
+    username: String,
+    password: String,
+    session: Option<String>,
+    keyboard_layout: Option<String>,
+) -> Result<LoginResult> {
     let mut stream = UnixStream::connect(env::var("GREETD_SOCK")?)?;
 
     let mut next_request = Request::CreateSession { username };
@@ -131,19 +365,8 @@ fn login(username: String, password: String, session: Option<String>) -> Result<
                 } else {
                     starting = true;
 
-                    let sessions = match sessions::get_sessions() {
-                        Ok(session) => session,
-                        Err(e) => return Err(anyhow!("error getting sessions, {e}")),
-                    };
-                    let session = if let Some(session_name) = &session {
-                        sessions.iter().find(|s| &s.name == session_name)
-                    } else {
-                        sessions.first()
-                    };
-                    let command = match session {
-                        Some(session) => session.get_session_command(),
-                        None => return Err(anyhow!("no sessoins available")),
-                    };
+                    let command = find_session(session.as_ref())?
+                        .get_session_command(keyboard_layout.as_deref());
                     next_request = Request::StartSession {
                         env: command.1,
                         cmd: vec![command.0],
@@ -167,3 +390,73 @@ fn login(username: String, password: String, session: Option<String>) -> Result<
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn client() -> GreeterClient {
+        GreeterClient::new(
+            None,
+            "team".to_string(),
+            "secret".to_string(),
+            false,
+            LoginThrottle::new(5, Duration::ZERO, Duration::from_secs(300)),
+        )
+    }
+
+    fn manual_login(client: &mut GreeterClient) -> u64 {
+        let _ = client.update(GreeterClientMessage::ManualLogin {
+            username: "contestant".to_string(),
+            password: "wrong".to_string(),
+            session: None,
+            keyboard_layout: None,
+        });
+        client.manual_login.as_ref().unwrap().0
+    }
+
+    fn result(client: &mut GreeterClient, attempt: u64, result: LoginResult) {
+        let _ = client.update(GreeterClientMessage::LoginResult {
+            attempt,
+            result: Ok(result),
+        });
+    }
+
+    #[test]
+    fn results_only_count_for_their_own_attempt() {
+        let mut client = client();
+        let manual = manual_login(&mut client);
+        let _ = client.update(GreeterClientMessage::Login {
+            reply: None,
+            wait: false,
+        });
+
+        result(
+            &mut client,
+            manual + 1,
+            LoginResult::Failure("auto".to_string()),
+        );
+        assert_eq!(client.throttle.failures(), 0);
+        assert!(client.manual_login.is_some());
+
+        result(
+            &mut client,
+            manual,
+            LoginResult::Failure("manual".to_string()),
+        );
+        assert_eq!(client.throttle.failures(), 1);
+        assert!(client.manual_login.is_none());
+    }
+
+    #[test]
+    fn dry_runs_do_not_count_as_failures() {
+        let mut client = client();
+        let attempt = manual_login(&mut client);
+
+        result(&mut client, attempt, LoginResult::DryRun);
+        assert_eq!(client.throttle.failures(), 0);
+        assert!(client.manual_login.is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+/// Longest time logins are blocked, whatever the configured delay or lockout.
+const MAX_BLOCK: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Slows down repeated failed manual logins. Every failure doubles the delay before the
+/// next attempt, and `max_attempts` failures in a row lock logins for `lockout`.
+#[derive(Debug)]
+pub struct LoginThrottle {
+    max_attempts: u32,
+    delay: Duration,
+    lockout: Duration,
+    failures: u32,
+    blocked_until: Option<Instant>,
+}
+
+impl LoginThrottle {
+    /// A `max_attempts` of 0 never locks, a zero `delay` never delays.
+    pub fn new(max_attempts: u32, delay: Duration, lockout: Duration) -> Self {
+        Self {
+            max_attempts,
+            delay,
+            lockout,
+            failures: 0,
+            blocked_until: None,
+        }
+    }
+
+    /// Keeps the current failures and block when the limits are changed.
+    pub fn reconfigure(&mut self, max_attempts: u32, delay: Duration, lockout: Duration) {
+        self.max_attempts = max_attempts;
+        self.delay = delay;
+        self.lockout = lockout;
+    }
+
+    /// Time until which new attempts are refused.
+    pub fn blocked_until(&self, now: Instant) -> Option<Instant> {
+        self.blocked_until.filter(|until| *until > now)
+    }
+
+    /// Number of failures since the last success or lockout.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Records a failed attempt and returns whether it caused a lockout.
+    pub fn record_failure(&mut self, now: Instant) -> bool {
+        self.failures += 1;
+        if self.max_attempts > 0 && self.failures >= self.max_attempts {
+            self.failures = 0;
+            self.blocked_until = block_until(now, self.lockout);
+            return true;
+        }
+        let factor = 2u32.saturating_pow(self.failures - 1);
+        self.blocked_until = block_until(now, self.delay.saturating_mul(factor));
+        false
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+        self.blocked_until = None;
+    }
+}
+
+fn block_until(now: Instant, duration: Duration) -> Option<Instant> {
+    now.checked_add(duration.min(MAX_BLOCK))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_the_delay_after_each_failure() {
+        let mut throttle = LoginThrottle::new(0, Duration::from_secs(1), Duration::from_secs(300));
+        let now = Instant::now();
+
+        assert_eq!(throttle.blocked_until(now), None);
+        for delay in [1, 2, 4, 8] {
+            assert!(!throttle.record_failure(now));
+            assert_eq!(
+                throttle.blocked_until(now),
+                Some(now + Duration::from_secs(delay))
+            );
+        }
+        assert_eq!(throttle.blocked_until(now + Duration::from_secs(8)), None);
+
+        throttle.record_success();
+        assert_eq!(throttle.blocked_until(now), None);
+        assert_eq!(throttle.failures(), 0);
+    }
+
+    #[test]
+    fn locks_out_after_max_attempts() {
+        let mut throttle = LoginThrottle::new(3, Duration::ZERO, Duration::from_secs(300));
+        let now = Instant::now();
+
+        assert!(!throttle.record_failure(now));
+        assert!(!throttle.record_failure(now));
+        assert_eq!(throttle.blocked_until(now), None);
+        assert!(throttle.record_failure(now));
+        assert_eq!(
+            throttle.blocked_until(now),
+            Some(now + Duration::from_secs(300))
+        );
+
+        // the attempts start over after the lockout
+        let later = now + Duration::from_secs(300);
+        assert_eq!(throttle.blocked_until(later), None);
+        assert!(!throttle.record_failure(later));
+    }
+
+    #[test]
+    fn caps_long_delays_and_lockouts() {
+        let mut throttle = LoginThrottle::new(2, Duration::from_secs(u64::MAX), Duration::MAX);
+        let now = Instant::now();
+
+        assert!(!throttle.record_failure(now));
+        assert_eq!(throttle.blocked_until(now), Some(now + MAX_BLOCK));
+        assert!(throttle.record_failure(now));
+        assert_eq!(throttle.blocked_until(now), Some(now + MAX_BLOCK));
+    }
+}
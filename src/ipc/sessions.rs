@@ -20,7 +20,8 @@ pub struct Session {
 }
 
 impl Session {
-    pub fn get_session_command(&self) -> (String, Vec<String>) {
+    /// Command and environment to start the session with, using the given keyboard layout.
+    pub fn get_session_command(&self, keyboard_layout: Option<&str>) -> (String, Vec<String>) {
         let mut env = Vec::new();
 
         if let Some(ref slug) = self.slug {
@@ -41,6 +42,10 @@ impl Session {
             env.push(format!("XDG_CURRENT_DESKTOP={}", desktop_names));
         }
 
+        if let Some(layout) = keyboard_layout {
+            env.push(format!("XKB_DEFAULT_LAYOUT={}", layout));
+        }
+
         (self.command.clone(), env)
     }
 }
@@ -1,36 +1,51 @@
-use crate::conf::{Conf, get_conf};
+use crate::cli::Cli;
+use crate::conf::load_conf;
+use crate::subscriptions::key_listener::KeyChain;
+use clap::Parser;
 use env_logger::Env;
 use log::warn;
-use std::env;
+use std::str::FromStr;
+mod cli;
 mod conf;
+mod host;
 mod ipc;
 mod subscriptions;
+mod template;
 mod ui;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let config_path = args
-        .get(1)
-        .map(|s| s.as_str())
-        .unwrap_or("/etc/greetd/contest-greeter.toml");
-
-    let config = match get_conf(config_path) {
-        Ok(config) => config,
-        Err(e) => {
-            warn!(
-                "failed to find config at {}, using default config: {e}",
-                config_path
-            );
-            Conf::new_default()
+    let cli = Cli::parse();
+
+    if let Some(chain) = &cli.hash_chain {
+        match KeyChain::from_str(chain).and_then(|chain| chain.hashed(KeyChain::DEFAULT_ITERATIONS))
+        {
+            Ok(hashed) => println!("{hashed}"),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
         }
-    };
+        return;
+    }
+
+    let config_path = cli.config_path();
+
+    let (mut config, skipped) = load_conf(&config_path, &cli.config_dir());
+
+    if let Some(log_level) = &cli.log_level {
+        config.log_level = log_level.clone();
+    }
 
     env_logger::Builder::from_env(
         Env::default().default_filter_or(format!("contest_greeter={}", config.log_level)),
     )
     .init();
 
-    if let Err(e) = ui::run_greeter(config) {
+    for error in skipped {
+        warn!("{error:#}");
+    }
+
+    if let Err(e) = ui::run_greeter(config, cli) {
         println!("error running app: {:?}", e)
     }
 }
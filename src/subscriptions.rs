@@ -1,3 +1,4 @@
 pub mod api_poller;
+pub mod config_watcher;
 pub mod dbus;
 pub mod key_listener;
@@ -3,22 +3,26 @@ use std::time::Duration;
 use anyhow::{Context, Result};
 use chrono::{DateTime, FixedOffset, Local};
 use iced::{Subscription, Task, time};
-use log::{debug, error};
+use log::{debug, error, info};
 use serde::Deserialize;
 
-use crate::ui::Message;
+use crate::ui::{Message, announcements::Announcement, broadcast::Notice};
 
 #[derive(Debug)]
 pub struct ApiPoller {
     url: Option<String>,
+    notice: Option<Notice>,
+    announcements: Vec<Announcement>,
 }
 
 #[derive(Clone, Debug)]
 pub enum ApiPollerMessage {
     FetchStartTime,
-    StartTimeFetched(Result<DateTime<Local>, String>),
+    Fetched(Result<ContestApiResponse, String>),
     SetUrl(Option<String>),
     SetStartime(DateTime<Local>),
+    SetNotice(Option<Notice>),
+    SetAnnouncements(Vec<Announcement>),
 }
 
 impl From<ApiPollerMessage> for Message {
@@ -29,7 +33,14 @@ impl From<ApiPollerMessage> for Message {
 
 impl ApiPoller {
     pub fn new(url: Option<String>) -> (Self, Task<ApiPollerMessage>) {
-        (Self { url }, Task::done(ApiPollerMessage::FetchStartTime))
+        (
+            Self {
+                url,
+                notice: None,
+                announcements: Vec::new(),
+            },
+            Task::done(ApiPollerMessage::FetchStartTime),
+        )
     }
 
     pub fn update(&mut self, msg: ApiPollerMessage) -> Task<ApiPollerMessage> {
@@ -39,19 +50,37 @@ impl ApiPoller {
                     return Task::perform(
                         async move {
                             tokio::task::spawn_blocking(move || {
-                                fetch_start_time(&url).map_err(|e| e.to_string())
+                                fetch_contest(&url).map_err(|e| e.to_string())
                             })
                             .await
                             .unwrap_or_else(|_| Err("Task panicked".to_string()))
                         },
-                        ApiPollerMessage::StartTimeFetched,
+                        ApiPollerMessage::Fetched,
                     );
                 }
                 return Task::none();
             }
             ApiPollerMessage::SetUrl(url) => self.url = url,
-            ApiPollerMessage::StartTimeFetched(result) => match result {
-                Ok(datetime) => return Task::done(ApiPollerMessage::SetStartime(datetime)),
+            ApiPollerMessage::Fetched(result) => match result {
+                Ok(response) => {
+                    let mut tasks = vec![Task::done(ApiPollerMessage::SetStartime(
+                        response.start_time.with_timezone(&Local),
+                    ))];
+                    // Only forward changes so a notice shown over D-Bus is not
+                    // replaced on every poll.
+                    if response.message != self.notice {
+                        self.notice = response.message.clone();
+                        tasks.push(Task::done(ApiPollerMessage::SetNotice(response.message)));
+                    }
+                    if response.announcements != self.announcements {
+                        log_announcement_changes(&self.announcements, &response.announcements);
+                        self.announcements = response.announcements.clone();
+                        tasks.push(Task::done(ApiPollerMessage::SetAnnouncements(
+                            response.announcements,
+                        )));
+                    }
+                    return Task::batch(tasks);
+                }
                 Err(error) => error!("failed getting starttime from api:{error}"),
             },
             _ => {}
@@ -64,13 +93,34 @@ impl ApiPoller {
     }
 }
 
-#[derive(Deserialize)]
-struct ContestApiResponse {
+#[derive(Clone, Debug, Deserialize)]
+pub struct ContestApiResponse {
     start_time: DateTime<FixedOffset>,
+    #[serde(default)]
+    message: Option<Notice>,
+    #[serde(default)]
+    announcements: Vec<Announcement>,
+}
+
+fn log_announcement_changes(old: &[Announcement], new: &[Announcement]) {
+    for announcement in new {
+        match old.iter().find(|a| a.id == announcement.id) {
+            None => info!("new announcement {}", announcement.id),
+            Some(previous) if previous != announcement => {
+                info!("updated announcement {}", announcement.id)
+            }
+            Some(_) => {}
+        }
+    }
+    for announcement in old {
+        if !new.iter().any(|a| a.id == announcement.id) {
+            info!("removed announcement {}", announcement.id);
+        }
+    }
 }
 
-fn fetch_start_time(url: &str) -> Result<DateTime<Local>> {
-    debug!("fetch start time from {url}");
+fn fetch_contest(url: &str) -> Result<ContestApiResponse> {
+    debug!("fetch contest info from {url}");
     let mut response = ureq::get(url)
         .call()
         .context(format!("sending request to {url}"))?;
@@ -80,5 +130,5 @@ fn fetch_start_time(url: &str) -> Result<DateTime<Local>> {
         .read_json()
         .context("decoding JSON payload")?;
 
-    Ok(payload.start_time.with_timezone(&Local))
+    Ok(payload)
 }
@@ -0,0 +1,125 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use iced::futures::SinkExt;
+use iced::futures::channel::mpsc::Sender;
+use iced::{Subscription, Task, stream, time};
+use log::{debug, error, info};
+use tokio::signal::unix::{SignalKind, signal};
+
+use crate::conf::{Conf, drop_in_files, get_conf};
+use crate::ui::Message;
+
+/// Reloads the config when one of its files changes or the greeter receives SIGHUP.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    drop_in_dir: PathBuf,
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ConfigWatcherMessage {
+    Check,
+    Reload,
+    Reloaded(Result<Box<Conf>, String>),
+    ConfigChanged(Box<Conf>),
+}
+
+impl From<ConfigWatcherMessage> for Message {
+    fn from(value: ConfigWatcherMessage) -> Self {
+        Message::ConfigWatcher(value)
+    }
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf, drop_in_dir: PathBuf) -> Self {
+        let modified = modification_times(&path, &drop_in_dir);
+        Self {
+            path,
+            drop_in_dir,
+            modified,
+        }
+    }
+
+    pub fn update(&mut self, msg: ConfigWatcherMessage) -> Task<ConfigWatcherMessage> {
+        match msg {
+            ConfigWatcherMessage::Check => {
+                let modified = modification_times(&self.path, &self.drop_in_dir);
+                if modified != self.modified {
+                    self.modified = modified;
+                    info!("config files changed, reloading config");
+                    return Task::done(ConfigWatcherMessage::Reload);
+                }
+            }
+            ConfigWatcherMessage::Reload => {
+                let path = self.path.clone();
+                let drop_in_dir = self.drop_in_dir.clone();
+                return Task::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || {
+                            get_conf(&path, &drop_in_dir)
+                                .map(Box::new)
+                                .map_err(|e| format!("{e:#}"))
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err("Task panicked".to_string()))
+                    },
+                    ConfigWatcherMessage::Reloaded,
+                );
+            }
+            ConfigWatcherMessage::Reloaded(result) => match result {
+                Ok(conf) => return Task::done(ConfigWatcherMessage::ConfigChanged(conf)),
+                Err(error) => error!("failed reloading config, keeping current config: {error}"),
+            },
+            ConfigWatcherMessage::ConfigChanged(_) => {
+                // handled by parent
+            }
+        }
+        Task::none()
+    }
+
+    pub fn subscription(&self) -> Subscription<ConfigWatcherMessage> {
+        Subscription::batch(vec![
+            time::every(Duration::from_secs(2)).map(|_| ConfigWatcherMessage::Check),
+            sighup_subscription(),
+        ])
+    }
+}
+
+fn sighup_subscription() -> Subscription<ConfigWatcherMessage> {
+    Subscription::run(|| {
+        stream::channel(1, |mut output: Sender<ConfigWatcherMessage>| async move {
+            let mut sighup = match signal(SignalKind::hangup()) {
+                Ok(sighup) => sighup,
+                Err(e) => {
+                    error!("failed to listen for SIGHUP: {e}");
+                    return;
+                }
+            };
+            while sighup.recv().await.is_some() {
+                info!("received SIGHUP, reloading config");
+                let _ = output.send(ConfigWatcherMessage::Reload).await;
+            }
+        })
+    })
+}
+
+fn modification_times(path: &Path, drop_in_dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files = vec![path.to_path_buf()];
+    match drop_in_files(drop_in_dir) {
+        Ok(drop_ins) => files.extend(drop_ins),
+        Err(e) => debug!("failed listing config drop-ins: {e}"),
+    }
+
+    files
+        .into_iter()
+        .map(|file| {
+            let modified = fs::metadata(&file).and_then(|m| m.modified()).ok();
+            (file, modified)
+        })
+        .collect()
+}
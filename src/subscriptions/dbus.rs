@@ -1,76 +1,861 @@
-use contest_greeter_dbus::{GreeterService, GreeterServiceBackend};
-use iced::Subscription;
+use std::pin::pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Local};
+use contest_greeter_dbus::{GreeterService, GreeterServiceBackend, GreeterState};
 use iced::futures::channel::mpsc::Sender;
-use iced::futures::{self, SinkExt};
+use iced::futures::future::{Either, select};
+use iced::futures::{self, SinkExt, StreamExt};
 use iced::stream;
-use log::{error, info};
+use iced::{Color, Subscription};
+use log::{error, info, warn};
+use tokio::net::UnixListener;
+use tokio::sync::{oneshot, watch};
 use zbus::conn::Builder;
+use zbus::fdo::{ConnectionCredentials, DBusProxy};
+use zbus::{Connection, Guid, fdo};
+
+use crate::{
+    conf::DbusBus,
+    host,
+    ui::broadcast::{Notice, Severity},
+};
+
+const RETRY_DELAY_MIN: Duration = Duration::from_secs(1);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(60);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Clone, Debug)]
 pub enum DbusMessage {
+    Connected(StatePublisher),
+    /// The service is not reachable, with the reason.
+    Unavailable(String),
     SetWallpaper(String),
-    Login,
+    Login {
+        wait: bool,
+        reply: DbusReply,
+    },
     SetApiUrl(String),
+    SetStartTime(DateTime<Local>),
+    ClearStartTime,
+    SetBackgroundLabel {
+        text: Option<String>,
+        color: Option<String>,
+    },
+    ShowMessage(Notice),
+    DismissMessage,
+    ToggleLoginForm,
+    ReloadConfig,
 }
 
-pub fn dbus_service_subscription() -> Subscription<DbusMessage> {
-    Subscription::run(|| {
-        stream::channel(16, |output: Sender<DbusMessage>| async move {
-            let greeter_service = GreeterService::new(GreeterDbusBackend {
-                sender: output.clone(),
-            });
+type ReplyResult = Result<(), String>;
 
-            let result = Builder::system()
-                .and_then(|b| b.name("nl.luukblankenstijn.ContestGreeterService"))
-                .and_then(|b| {
-                    b.serve_at(
-                        "/nl/luukblankenstijn/ContestGreeterService",
-                        greeter_service,
-                    )
-                });
-
-            match result {
-                Ok(builder) => match builder.build().await {
-                    Ok(_connection) => {
-                        info!(
-                            "[DBus-Service] Service started: nl.luukblankenstijn.ContestGreeterService"
-                        );
-                        std::future::pending::<()>().await;
-                    }
-                    Err(e) => {
-                        error!("[DBus-Service] Failed to build D-Bus connection: {}", e);
-                    }
-                },
-                Err(e) => {
-                    error!("[DBus-Service] configuration failed: {}", e);
+/// One-shot channel to answer a D-Bus call from the greeter.
+#[derive(Clone, Debug)]
+pub struct DbusReply(Arc<Mutex<Option<oneshot::Sender<ReplyResult>>>>);
+
+impl DbusReply {
+    fn new() -> (Self, oneshot::Receiver<ReplyResult>) {
+        let (sender, receiver) = oneshot::channel();
+        (Self(Arc::new(Mutex::new(Some(sender)))), receiver)
+    }
+
+    /// Answers the call. Only the first answer is sent.
+    pub fn send(&self, result: ReplyResult) {
+        if let Some(sender) = self.0.lock().ok().and_then(|mut sender| sender.take()) {
+            let _ = sender.send(result);
+        }
+    }
+}
+
+/// Handle to publish the greeter state as D-Bus properties.
+#[derive(Clone, Debug)]
+pub struct StatePublisher(watch::Sender<GreeterState>);
+
+impl StatePublisher {
+    pub fn publish(&self, state: GreeterState) {
+        self.0.send_if_modified(|current| {
+            if *current == state {
+                return false;
+            }
+            *current = state;
+            true
+        });
+    }
+}
+
+/// Users and groups allowed to call the D-Bus methods. Root is always allowed.
+#[derive(Clone, Debug, Hash)]
+pub struct AccessPolicy {
+    users: Vec<String>,
+    groups: Vec<String>,
+    allow_root: bool,
+}
+
+impl AccessPolicy {
+    pub fn new(users: Vec<String>, groups: Vec<String>) -> Self {
+        Self {
+            users,
+            groups,
+            allow_root: true,
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.users.is_empty() && self.groups.is_empty()
+    }
+
+    /// Whether the caller may call the service methods. Users and groups are given by name
+    /// or numeric id. `gids` are the caller's groups if the bus reported them, otherwise
+    /// they are looked up from `uid`.
+    fn allows(&self, uid: u32, gids: Option<&Vec<u32>>, accounts: &impl Accounts) -> bool {
+        if (uid == 0 && self.allow_root) || self.is_open() {
+            return true;
+        }
+
+        let user_allowed = self
+            .users
+            .iter()
+            .any(|user| resolve(user, "user", |name| accounts.user_id(name)) == Some(uid));
+        if user_allowed {
+            return true;
+        }
+
+        let gids = gids.cloned().unwrap_or_else(|| accounts.group_ids(uid));
+        self.groups.iter().any(|group| {
+            resolve(group, "group", |name| accounts.group_id(name))
+                .is_some_and(|gid| gids.contains(&gid))
+        })
+    }
+}
+
+fn resolve(name: &str, kind: &str, lookup: impl Fn(&str) -> Option<u32>) -> Option<u32> {
+    let id = name.parse().ok().or_else(|| lookup(name));
+    if id.is_none() {
+        warn!("[DBus-Service] unknown {kind} {name} in the access policy");
+    }
+    id
+}
+
+/// User and group lookups used by [`AccessPolicy`].
+trait Accounts {
+    fn user_id(&self, name: &str) -> Option<u32>;
+    fn group_id(&self, name: &str) -> Option<u32>;
+    fn group_ids(&self, uid: u32) -> Vec<u32>;
+}
+
+/// Looks users and groups up on this machine through NSS.
+struct HostAccounts;
+
+impl Accounts for HostAccounts {
+    fn user_id(&self, name: &str) -> Option<u32> {
+        host::user_id(name)
+    }
+
+    fn group_id(&self, name: &str) -> Option<u32> {
+        host::group_id(name)
+    }
+
+    fn group_ids(&self, uid: u32) -> Vec<u32> {
+        host::group_ids(uid)
+    }
+}
+
+/// Where the D-Bus service is offered and who may call it.
+#[derive(Clone, Debug, Hash)]
+pub struct DbusSettings {
+    pub bus: DbusBus,
+    pub socket_path: Option<String>,
+    pub name: String,
+    pub path: String,
+    pub policy: AccessPolicy,
+}
+
+pub fn dbus_service_subscription(settings: DbusSettings) -> Subscription<DbusMessage> {
+    Subscription::run_with(settings, |settings| {
+        let settings = settings.clone();
+        stream::channel(16, |mut output: Sender<DbusMessage>| async move {
+            if settings.policy.is_open() {
+                warn!(
+                    "[DBus-Service] no allowed users or groups configured, every user can call the service"
+                );
+            }
+
+            // the bus may not be up yet at boot, so keep retrying with backoff
+            let mut delay = RETRY_DELAY_MIN;
+            loop {
+                let mut connected = false;
+                let result = serve(&settings, output.clone(), &mut connected).await;
+                // only back off while the service never comes up
+                if connected {
+                    delay = RETRY_DELAY_MIN;
                 }
+                let reason = match result {
+                    Ok(()) => "connection to the bus lost".to_string(),
+                    Err(e) => format!("{e:#}"),
+                };
+                error!("[DBus-Service] {reason}, retrying in {}s", delay.as_secs());
+                let _ = output.send(DbusMessage::Unavailable(reason)).await;
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(RETRY_DELAY_MAX);
             }
         })
     })
 }
 
+/// Serves the service until the connection or the bus name is lost. `connected` is set once
+/// the service is offered.
+async fn serve(
+    settings: &DbusSettings,
+    mut output: Sender<DbusMessage>,
+    connected: &mut bool,
+) -> Result<()> {
+    let (state_sender, state_receiver) = watch::channel(GreeterState::default());
+    let new_service = |output: &Sender<DbusMessage>| {
+        GreeterService::new(GreeterDbusBackend {
+            sender: output.clone(),
+            policy: settings.policy.clone(),
+        })
+    };
+
+    if settings.bus == DbusBus::Peer {
+        let socket_path = settings
+            .socket_path
+            .as_deref()
+            .context("dbus_socket_path is required for the peer bus")?;
+        // remove the socket left behind by a previous run
+        let _ = std::fs::remove_file(socket_path);
+        let listener = UnixListener::bind(socket_path).context(format!("binding {socket_path}"))?;
+        info!("[DBus-Service] Service listening on {socket_path}");
+        output
+            .send(DbusMessage::Connected(StatePublisher(state_sender)))
+            .await?;
+        *connected = true;
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let service = new_service(&output);
+            let path = settings.path.clone();
+            let receiver = state_receiver.clone();
+            // a client that stalls in the handshake must not block the next one
+            tokio::spawn(async move {
+                let handshake = async {
+                    Builder::unix_stream(stream)
+                        .p2p()
+                        .server(Guid::generate())?
+                        .serve_at(path.as_str(), service)?
+                        .build()
+                        .await
+                };
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                    Ok(Ok(connection)) => publish_state(&connection, &path, receiver).await,
+                    Ok(Err(e)) => warn!("[DBus-Service] Failed to accept peer connection: {}", e),
+                    Err(_) => warn!("[DBus-Service] Peer connection timed out in the handshake"),
+                }
+            });
+        }
+    }
+
+    let builder = match settings.bus {
+        DbusBus::Session => Builder::session()?,
+        _ => Builder::system()?,
+    };
+    let connection = builder
+        .name(settings.name.as_str())?
+        .serve_at(settings.path.as_str(), new_service(&output))?
+        .build()
+        .await
+        .context("building D-Bus connection")?;
+    let mut name_lost_signals = DBusProxy::new(&connection)
+        .await?
+        .receive_name_lost()
+        .await?;
+    let name_lost = async {
+        while let Some(signal) = name_lost_signals.next().await {
+            if signal
+                .args()
+                .is_ok_and(|args| args.name().as_str() == settings.name)
+            {
+                return;
+            }
+        }
+    };
+    info!("[DBus-Service] Service started: {}", settings.name);
+    output
+        .send(DbusMessage::Connected(StatePublisher(state_sender)))
+        .await?;
+    *connected = true;
+
+    let publish = publish_state(&connection, &settings.path, state_receiver);
+    match select(pin!(publish), pin!(name_lost)).await {
+        Either::Left(_) => Ok(()),
+        Either::Right(_) => Err(anyhow!("lost the bus name {}", settings.name)),
+    }
+}
+
+async fn publish_state(
+    connection: &Connection,
+    path: &str,
+    mut receiver: watch::Receiver<GreeterState>,
+) {
+    let iface = match connection
+        .object_server()
+        .interface::<_, GreeterService<GreeterDbusBackend>>(path)
+        .await
+    {
+        Ok(iface) => iface,
+        Err(e) => {
+            error!("[DBus-Service] Failed to get interface: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        let state = receiver.borrow_and_update().clone();
+        if let Err(e) = iface
+            .get_mut()
+            .await
+            .update_state(state, iface.signal_emitter())
+            .await
+        {
+            error!(
+                "[DBus-Service] Failed to emit property changes, stopping updates: {}",
+                e
+            );
+            return;
+        }
+        if receiver.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
 struct GreeterDbusBackend {
     sender: futures::channel::mpsc::Sender<DbusMessage>,
+    policy: AccessPolicy,
+}
+
+impl GreeterDbusBackend {
+    async fn send(&self, msg: DbusMessage) -> fdo::Result<()> {
+        self.sender
+            .clone()
+            .send(msg)
+            .await
+            .map_err(|_| fdo::Error::Failed("greeter is not running".to_string()))
+    }
 }
 
 impl GreeterServiceBackend for GreeterDbusBackend {
-    fn set_wallpaper_source(&self, url: String) {
-        let mut sender = self.sender.clone();
-        tokio::spawn(async move {
-            let _ = sender.send(DbusMessage::SetWallpaper(url)).await;
-        });
+    fn authorize(&self, credentials: &ConnectionCredentials) -> fdo::Result<()> {
+        let uid = credentials
+            .unix_user_id()
+            .ok_or_else(|| fdo::Error::AccessDenied("caller uid is unknown".to_string()))?;
+        if self
+            .policy
+            .allows(uid, credentials.unix_group_ids(), &HostAccounts)
+        {
+            return Ok(());
+        }
+        warn!("[DBus-Service] denied call from uid {uid}");
+        Err(fdo::Error::AccessDenied(format!(
+            "uid {uid} is not allowed to control the greeter"
+        )))
     }
 
-    fn set_api_poller_url(&self, url: String) {
-        let mut sender = self.sender.clone();
-        tokio::spawn(async move {
-            let _ = sender.send(DbusMessage::SetApiUrl(url)).await;
-        });
+    async fn set_wallpaper_source(&self, url: String) -> fdo::Result<()> {
+        if url.is_empty() {
+            return Err(fdo::Error::InvalidArgs(
+                "wallpaper source can not be empty".to_string(),
+            ));
+        }
+        self.send(DbusMessage::SetWallpaper(url)).await
     }
 
-    fn login(&self) {
-        let mut sender = self.sender.clone();
-        tokio::spawn(async move {
-            let _ = sender.send(DbusMessage::Login).await;
-        });
+    async fn set_api_poller_url(&self, url: String) -> fdo::Result<()> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "api url must be an http url: {url}"
+            )));
+        }
+        self.send(DbusMessage::SetApiUrl(url)).await
+    }
+
+    async fn login(&self, wait: bool) -> fdo::Result<()> {
+        let (reply, result) = DbusReply::new();
+        self.send(DbusMessage::Login { wait, reply }).await?;
+        result
+            .await
+            .map_err(|_| fdo::Error::Failed("greeter did not answer".to_string()))?
+            .map_err(fdo::Error::Failed)
+    }
+
+    async fn set_start_time(&self, start_time: String) -> fdo::Result<()> {
+        let start_time = DateTime::parse_from_rfc3339(&start_time).map_err(|e| {
+            fdo::Error::InvalidArgs(format!("invalid start time {start_time}: {e}"))
+        })?;
+        self.send(DbusMessage::SetStartTime(start_time.with_timezone(&Local)))
+            .await
+    }
+
+    async fn clear_start_time(&self) -> fdo::Result<()> {
+        self.send(DbusMessage::ClearStartTime).await
+    }
+
+    async fn set_background_label(&self, text: String, color: String) -> fdo::Result<()> {
+        if !color.is_empty() && Color::from_str(&color).is_err() {
+            return Err(fdo::Error::InvalidArgs(format!("invalid color: {color}")));
+        }
+        self.send(DbusMessage::SetBackgroundLabel {
+            text: (!text.is_empty()).then_some(text),
+            color: (!color.is_empty()).then_some(color),
+        })
+        .await
+    }
+
+    async fn show_message(
+        &self,
+        title: String,
+        body: String,
+        severity: String,
+        expires_in: u32,
+    ) -> fdo::Result<()> {
+        let severity = Severity::from_str(&severity).map_err(fdo::Error::InvalidArgs)?;
+        let expires_at =
+            (expires_in > 0).then(|| Local::now() + chrono::Duration::seconds(expires_in.into()));
+        self.send(DbusMessage::ShowMessage(Notice {
+            title,
+            body,
+            severity,
+            expires_at,
+        }))
+        .await
+    }
+
+    async fn dismiss_message(&self) -> fdo::Result<()> {
+        self.send(DbusMessage::DismissMessage).await
+    }
+
+    async fn toggle_login_form(&self) -> fdo::Result<()> {
+        self.send(DbusMessage::ToggleLoginForm).await
+    }
+
+    async fn reload_config(&self) -> fdo::Result<()> {
+        self.send(DbusMessage::ReloadConfig).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use contest_greeter_dbus::GreeterServiceProxy;
+    use iced::futures::channel::mpsc::{Receiver, channel};
+    use tokio::net::UnixStream;
+    use zbus::fdo::IntrospectableProxy;
+    use zbus::proxy::CacheProperties;
+
+    use super::*;
+
+    const PATH: &str = "/nl/luukblankenstijn/ContestGreeterService";
+
+    /// Serves the service over a private peer-to-peer connection and returns the server
+    /// connection, the client connection and the messages sent to the greeter.
+    async fn serve() -> (Connection, Connection, Receiver<DbusMessage>) {
+        serve_with(AccessPolicy::new(vec![], vec![])).await
+    }
+
+    async fn serve_with(policy: AccessPolicy) -> (Connection, Connection, Receiver<DbusMessage>) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let (sender, messages) = channel(16);
+        let service = GreeterService::new(GreeterDbusBackend { sender, policy });
+
+        let server = Builder::unix_stream(server_stream)
+            .p2p()
+            .server(Guid::generate())
+            .unwrap()
+            .serve_at(PATH, service)
+            .unwrap()
+            .build();
+        let client = Builder::unix_stream(client_stream).p2p().build();
+        let (server, client) = futures::try_join!(server, client).unwrap();
+        (server, client, messages)
+    }
+
+    async fn proxy(client: &Connection) -> GreeterServiceProxy<'_> {
+        GreeterServiceProxy::builder(client)
+            .path(PATH)
+            .unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    fn is_invalid_args(error: fdo::Error) -> bool {
+        matches!(error, fdo::Error::InvalidArgs(_))
+    }
+
+    #[tokio::test]
+    async fn forwards_method_calls_as_messages() {
+        let (_server, client, mut messages) = serve().await;
+        let proxy = proxy(&client).await;
+
+        proxy
+            .set_wallpaper_source("/tmp/wallpaper.png".to_string())
+            .await
+            .unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::SetWallpaper(source)) if source == "/tmp/wallpaper.png"
+        ));
+
+        proxy
+            .set_api_poller_url("https://contest.example/api".to_string())
+            .await
+            .unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::SetApiUrl(url)) if url == "https://contest.example/api"
+        ));
+
+        proxy
+            .set_start_time("2030-01-01T10:00:00Z".to_string())
+            .await
+            .unwrap();
+        let expected = DateTime::parse_from_rfc3339("2030-01-01T10:00:00Z").unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::SetStartTime(start_time)) if start_time == expected
+        ));
+
+        proxy.clear_start_time().await.unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::ClearStartTime)
+        ));
+
+        proxy
+            .set_background_label("team 1".to_string(), "".to_string())
+            .await
+            .unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::SetBackgroundLabel { text: Some(text), color: None }) if text == "team 1"
+        ));
+
+        proxy
+            .show_message(
+                "Delay".to_string(),
+                "15 minutes".to_string(),
+                "warning".to_string(),
+                60,
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::ShowMessage(Notice { title, body, severity: Severity::Warning, expires_at: Some(_) }))
+                if title == "Delay" && body == "15 minutes"
+        ));
+
+        proxy.dismiss_message().await.unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::DismissMessage)
+        ));
+
+        proxy.toggle_login_form().await.unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::ToggleLoginForm)
+        ));
+
+        proxy.reload_config().await.unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::ReloadConfig)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_arguments() {
+        let (_server, client, _messages) = serve().await;
+        let proxy = proxy(&client).await;
+
+        let error = proxy
+            .set_start_time("tomorrow".to_string())
+            .await
+            .unwrap_err();
+        assert!(is_invalid_args(error));
+
+        let error = proxy
+            .set_api_poller_url("ftp://contest.example".to_string())
+            .await
+            .unwrap_err();
+        assert!(is_invalid_args(error));
+
+        let error = proxy
+            .set_background_label("team 1".to_string(), "not a color".to_string())
+            .await
+            .unwrap_err();
+        assert!(is_invalid_args(error));
+
+        let error = proxy
+            .show_message("Delay".to_string(), "".to_string(), "urgent".to_string(), 0)
+            .await
+            .unwrap_err();
+        assert!(is_invalid_args(error));
+    }
+
+    #[tokio::test]
+    async fn login_returns_the_greeter_result() {
+        let (_server, client, mut messages) = serve().await;
+        let proxy = proxy(&client).await;
+
+        for (wait, result) in [
+            (false, Ok(())),
+            (true, Err("wrong username or password".to_string())),
+        ] {
+            let expected = result.clone();
+            let greeter = async {
+                let Some(DbusMessage::Login {
+                    wait: received_wait,
+                    reply,
+                }) = messages.next().await
+                else {
+                    panic!("expected a login message");
+                };
+                assert_eq!(received_wait, wait);
+                reply.send(result);
+            };
+
+            let (response, ()) = tokio::join!(
+                async {
+                    if wait {
+                        proxy.login_and_wait().await
+                    } else {
+                        proxy.login().await
+                    }
+                },
+                greeter
+            );
+            match expected {
+                Ok(()) => response.unwrap(),
+                Err(expected) => assert!(matches!(
+                    response.unwrap_err(),
+                    fdo::Error::Failed(msg) if msg == expected
+                )),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn exposes_state_as_properties() {
+        let (server, client, _messages) = serve().await;
+        let proxy = proxy(&client).await;
+
+        let iface = server
+            .object_server()
+            .interface::<_, GreeterService<GreeterDbusBackend>>(PATH)
+            .await
+            .unwrap();
+        let state = GreeterState {
+            start_time: "2030-01-01T10:00:00+00:00".to_string(),
+            countdown_state: "scheduled".to_string(),
+            wallpaper_status: "ready".to_string(),
+            last_login_error: "wrong username or password".to_string(),
+            session: "Sway".to_string(),
+            version: "1.2.3".to_string(),
+        };
+        iface
+            .get_mut()
+            .await
+            .update_state(state.clone(), iface.signal_emitter())
+            .await
+            .unwrap();
+
+        assert_eq!(proxy.start_time().await.unwrap(), state.start_time);
+        assert_eq!(
+            proxy.countdown_state().await.unwrap(),
+            state.countdown_state
+        );
+        assert_eq!(
+            proxy.wallpaper_status().await.unwrap(),
+            state.wallpaper_status
+        );
+        assert_eq!(
+            proxy.last_login_error().await.unwrap(),
+            state.last_login_error
+        );
+        assert_eq!(proxy.session().await.unwrap(), state.session);
+        assert_eq!(proxy.version().await.unwrap(), state.version);
+    }
+
+    #[tokio::test]
+    async fn introspection_lists_the_interface() {
+        let (_server, client, _messages) = serve().await;
+        let introspectable = IntrospectableProxy::builder(&client)
+            .destination("nl.luukblankenstijn.ContestGreeterService")
+            .unwrap()
+            .path(PATH)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let xml = introspectable.introspect().await.unwrap();
+
+        assert!(xml.contains(r#"<interface name="nl.luukblankenstijn.ContestGreeterService">"#));
+        for method in [
+            "SetWallpaperSource",
+            "SetApiPollerUrl",
+            "Login",
+            "LoginAndWait",
+            "SetStartTime",
+            "ClearStartTime",
+            "SetBackgroundLabel",
+            "ShowMessage",
+            "DismissMessage",
+            "ToggleLoginForm",
+            "ReloadConfig",
+        ] {
+            assert!(
+                xml.contains(&format!(r#"<method name="{method}">"#)),
+                "missing method {method}"
+            );
+        }
+        for property in [
+            "StartTime",
+            "CountdownState",
+            "WallpaperStatus",
+            "LastLoginError",
+            "Session",
+            "Version",
+        ] {
+            assert!(
+                xml.contains(&format!(
+                    r#"<property name="{property}" type="s" access="read"/>"#
+                )),
+                "missing property {property}"
+            );
+        }
+    }
+
+    /// Users `alice` (1000, groups 1000 and 27) and `bob` (1001, group 1001).
+    struct FakeAccounts;
+
+    impl Accounts for FakeAccounts {
+        fn user_id(&self, name: &str) -> Option<u32> {
+            match name {
+                "alice" => Some(1000),
+                "bob" => Some(1001),
+                _ => None,
+            }
+        }
+
+        fn group_id(&self, name: &str) -> Option<u32> {
+            match name {
+                "sudo" => Some(27),
+                "bob" => Some(1001),
+                _ => None,
+            }
+        }
+
+        fn group_ids(&self, uid: u32) -> Vec<u32> {
+            match uid {
+                1000 => vec![1000, 27],
+                1001 => vec![1001],
+                _ => vec![],
+            }
+        }
+    }
+
+    fn policy(users: &[&str], groups: &[&str]) -> AccessPolicy {
+        AccessPolicy::new(
+            users.iter().map(|user| user.to_string()).collect(),
+            groups.iter().map(|group| group.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn policy_allows_listed_users_by_name_or_uid() {
+        for users in [["alice"], ["1000"]] {
+            let policy = policy(&users, &[]);
+            assert!(policy.allows(1000, Some(&vec![]), &FakeAccounts));
+            assert!(!policy.allows(1001, Some(&vec![]), &FakeAccounts));
+        }
+        assert!(!policy(&["mallory"], &[]).allows(1000, Some(&vec![]), &FakeAccounts));
+    }
+
+    #[test]
+    fn policy_allows_listed_groups_by_name_or_gid() {
+        for groups in [["sudo"], ["27"]] {
+            let policy = policy(&[], &groups);
+            assert!(policy.allows(1001, Some(&vec![1001, 27]), &FakeAccounts));
+            assert!(!policy.allows(1001, Some(&vec![1001]), &FakeAccounts));
+        }
+        assert!(!policy(&[], &["wheel"]).allows(1000, Some(&vec![27]), &FakeAccounts));
+    }
+
+    #[test]
+    fn policy_looks_up_groups_when_the_bus_does_not_report_them() {
+        let policy = policy(&[], &["sudo"]);
+        assert!(policy.allows(1000, None, &FakeAccounts));
+        assert!(!policy.allows(1001, None, &FakeAccounts));
+    }
+
+    #[test]
+    fn policy_allows_root_and_everyone_when_open() {
+        assert!(policy(&["alice"], &["sudo"]).allows(0, Some(&vec![]), &FakeAccounts));
+        assert!(policy(&[], &[]).allows(1001, Some(&vec![]), &FakeAccounts));
+    }
+
+    #[tokio::test]
+    async fn denied_callers_get_access_denied() {
+        let uid = nix::unistd::getuid().as_raw();
+        let mut policy = policy(&[&(uid + 1).to_string()], &[]);
+        policy.allow_root = false;
+        let (_server, client, _messages) = serve_with(policy).await;
+        let proxy = proxy(&client).await;
+
+        assert!(matches!(
+            proxy.toggle_login_form().await.unwrap_err(),
+            fdo::Error::AccessDenied(_)
+        ));
+        // reading properties is always allowed
+        proxy.version().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn peer_socket_serves_clients_while_another_stalls() {
+        let socket_path =
+            std::env::temp_dir().join(format!("contest-greeter-dbus-{}.sock", std::process::id()));
+        let settings = DbusSettings {
+            bus: DbusBus::Peer,
+            socket_path: Some(socket_path.to_string_lossy().to_string()),
+            name: "nl.luukblankenstijn.ContestGreeterService".to_string(),
+            path: PATH.to_string(),
+            policy: AccessPolicy::new(vec![], vec![]),
+        };
+        let (sender, mut messages) = channel(16);
+        tokio::spawn(async move { super::serve(&settings, sender, &mut false).await });
+        // the connections are closed once the state publisher is dropped
+        let Some(DbusMessage::Connected(_publisher)) = messages.next().await else {
+            panic!("expected the service to be connected");
+        };
+
+        // connects but never starts the handshake
+        let _stalled = UnixStream::connect(&socket_path).await.unwrap();
+        let client = Builder::unix_stream(UnixStream::connect(&socket_path).await.unwrap())
+            .p2p()
+            .build()
+            .await
+            .unwrap();
+        proxy(&client).await.toggle_login_form().await.unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(DbusMessage::ToggleLoginForm)
+        ));
+        let _ = std::fs::remove_file(&socket_path);
     }
 }
@@ -1,55 +1,484 @@
-use std::collections::VecDeque;
+mod chain;
 
-use iced::{Subscription, Task};
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use iced::{
+    Subscription, Task,
+    keyboard::{self, Key, Modifiers, key::Named},
+};
+use log::error;
+
+pub use chain::KeyChain;
+use chain::{ChainKey, KeyPress};
+
+use crate::conf::ChainAction;
+
+fn is_modifier(named: Named) -> bool {
+    matches!(
+        named,
+        Named::Control | Named::Alt | Named::AltGraph | Named::Shift | Named::Super | Named::Meta
+    )
+}
 
 #[derive(Debug)]
 pub struct KeyListener {
-    buffer: VecDeque<char>,
-    chain: String,
+    buffer: VecDeque<KeyPress>,
+    chains: Vec<(KeyChain, ChainAction)>,
+    timeout: Duration,
+    last_press: Option<Instant>,
 }
 
 #[derive(Clone, Debug)]
 pub enum KeyListenerMessage {
     Event(iced::event::Event),
-    ChainTriggered,
+    // the variants below are handled by the parent
+    ToggleForm,
+    AutoLogin,
+    ToggleDiagnostics,
+    ReloadConfig,
+    RefetchWallpaper,
+}
+
+impl From<ChainAction> for KeyListenerMessage {
+    fn from(action: ChainAction) -> Self {
+        match action {
+            ChainAction::ToggleForm => KeyListenerMessage::ToggleForm,
+            ChainAction::AutoLogin => KeyListenerMessage::AutoLogin,
+            ChainAction::Diagnostics => KeyListenerMessage::ToggleDiagnostics,
+            ChainAction::ReloadConfig => KeyListenerMessage::ReloadConfig,
+            ChainAction::RefetchWallpaper => KeyListenerMessage::RefetchWallpaper,
+        }
+    }
 }
 
 impl KeyListener {
-    pub fn new(chain: String) -> Self {
+    pub fn new(chains: Vec<(String, ChainAction)>, timeout: Duration) -> Self {
+        let chains = chains
+            .into_iter()
+            .filter_map(|(chain, action)| {
+                KeyChain::from_str(&chain)
+                    .inspect_err(|e| error!("invalid key chain {chain:?}: {e}"))
+                    .ok()
+                    .map(|chain| (chain, action))
+            })
+            .collect();
         Self {
             buffer: VecDeque::new(),
-            chain,
+            chains,
+            timeout,
+            last_press: None,
         }
     }
+
     pub fn update(&mut self, msg: KeyListenerMessage) -> Task<KeyListenerMessage> {
-        match msg {
-            KeyListenerMessage::Event(event) => {
-                if let iced::event::Event::Keyboard(iced::keyboard::Event::KeyPressed {
-                    key,
-                    physical_key,
-                    ..
-                }) = event
-                    && let Some(key) = key.to_latin(physical_key)
-                {
-                    self.buffer.push_back(key);
-                    if self.buffer.len() != self.chain.len() {
-                        return Task::none();
-                    }
-                    let buffer_string = String::from_iter(self.buffer.clone());
-                    self.buffer.pop_front();
-                    if buffer_string == self.chain {
-                        return Task::done(KeyListenerMessage::ChainTriggered);
-                    }
+        if let KeyListenerMessage::Event(iced::event::Event::Keyboard(
+            keyboard::Event::KeyPressed {
+                key,
+                modified_key,
+                physical_key,
+                modifiers,
+                repeat: false,
+                ..
+            },
+        )) = msg
+            && let Some(action) =
+                self.key_pressed(key, modified_key, physical_key, modifiers, Instant::now())
+        {
+            return Task::done(action.into());
+        }
+        Task::none()
+    }
+
+    /// Adds a key press to the buffer and returns the action of the chain it completed.
+    /// `modified_key` is the character with shift applied, `key` the one without.
+    fn key_pressed(
+        &mut self,
+        key: Key,
+        modified_key: Key,
+        physical_key: keyboard::key::Physical,
+        modifiers: Modifiers,
+        now: Instant,
+    ) -> Option<ChainAction> {
+        if self.chains.is_empty() {
+            return None;
+        }
+
+        let latin = key.to_latin(physical_key).map(ChainKey::from_char);
+        let key = match (&key, &modified_key) {
+            (Key::Named(named), _) if is_modifier(*named) => return None,
+            (Key::Named(named), _) => Some(ChainKey::Named(*named)),
+            (_, Key::Character(text)) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(ChainKey::from_char(c)),
+                    _ => None,
                 }
             }
-            KeyListenerMessage::ChainTriggered => {
-                // handled by parent
-            }
+            _ => None,
+        };
+
+        if self
+            .last_press
+            .is_some_and(|last| !self.timeout.is_zero() && now - last > self.timeout)
+        {
+            self.buffer.clear();
         }
-        Task::none()
+        self.last_press = Some(now);
+
+        // keys that can not be part of a chain break the sequence
+        let Some(key) = key.or_else(|| latin.clone()) else {
+            self.buffer.clear();
+            return None;
+        };
+
+        let modifiers =
+            modifiers & (Modifiers::CTRL | Modifiers::ALT | Modifiers::SHIFT | Modifiers::LOGO);
+        self.buffer.push_back(KeyPress::new(modifiers, key, latin));
+        let longest = self.chains.iter().map(|(chain, _)| chain.len()).max()?;
+        if self.buffer.len() > longest {
+            self.buffer.pop_front();
+        }
+
+        let action = self.chains.iter().find_map(|(chain, action)| {
+            let start = self.buffer.len().checked_sub(chain.len())?;
+            chain.matches(self.buffer.range(start..)).then_some(*action)
+        })?;
+        self.buffer.clear();
+        Some(action)
     }
 
     pub fn subscription(&self) -> Subscription<KeyListenerMessage> {
         iced::event::listen().map(KeyListenerMessage::Event)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use iced::keyboard::key::{Code, NativeCode, Physical};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn listener(chains: &[(&str, ChainAction)]) -> KeyListener {
+        KeyListener::new(
+            chains
+                .iter()
+                .map(|(chain, action)| (chain.to_string(), *action))
+                .collect(),
+            TIMEOUT,
+        )
+    }
+
+    /// Presses a key on a US layout, like the event iced sends: `key` without and
+    /// `modified_key` with shift applied.
+    fn press(
+        listener: &mut KeyListener,
+        key: &str,
+        modifiers: Modifiers,
+        now: Instant,
+    ) -> Option<ChainAction> {
+        let (key, modified_key) = match key {
+            "F12" => (Key::Named(Named::F12), Key::Named(Named::F12)),
+            "Enter" => (Key::Named(Named::Enter), Key::Named(Named::Enter)),
+            "Control" => (Key::Named(Named::Control), Key::Named(Named::Control)),
+            _ if modifiers.shift() => {
+                let shifted = match key {
+                    "2" => "@".to_string(),
+                    key => key.to_uppercase(),
+                };
+                (Key::Character(key.into()), Key::Character(shifted.into()))
+            }
+            _ => (Key::Character(key.into()), Key::Character(key.into())),
+        };
+        listener.key_pressed(
+            key,
+            modified_key,
+            Physical::Unidentified(NativeCode::Unidentified),
+            modifiers,
+            now,
+        )
+    }
+
+    /// Types `keys` one after another, each given as `<key>` or `<modifiers>+<key>` with
+    /// named keys in their config spelling and characters as on the unshifted key.
+    /// Returns the actions that were triggered.
+    fn type_keys(listener: &mut KeyListener, keys: &str) -> Vec<ChainAction> {
+        let now = Instant::now();
+        keys.split_whitespace()
+            .filter_map(|combo| {
+                let mut parts: Vec<&str> = combo.split('+').collect();
+                let key = parts.pop().unwrap();
+                let modifiers = parts
+                    .into_iter()
+                    .fold(Modifiers::empty(), |modifiers, name| {
+                        modifiers
+                            | match name {
+                                "ctrl" => Modifiers::CTRL,
+                                "alt" => Modifiers::ALT,
+                                "shift" => Modifiers::SHIFT,
+                                _ => panic!("unknown modifier {name}"),
+                            }
+                    });
+                press(listener, key, modifiers, now)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plain_string_matches_per_character() {
+        let mut listener = listener(&[("chain", ChainAction::ToggleForm)]);
+
+        assert_eq!(type_keys(&mut listener, "c h a i"), vec![]);
+        assert_eq!(type_keys(&mut listener, "n"), vec![ChainAction::ToggleForm]);
+    }
+
+    #[test]
+    fn plain_strings_that_are_key_names_match_per_character() {
+        let mut listener = listener(&[("end", ChainAction::ToggleForm)]);
+
+        assert_eq!(type_keys(&mut listener, "e n"), vec![]);
+        assert_eq!(type_keys(&mut listener, "d"), vec![ChainAction::ToggleForm]);
+    }
+
+    #[test]
+    fn matches_chains_with_overlapping_prefixes() {
+        let mut listener = listener(&[("a a b", ChainAction::ToggleForm)]);
+
+        assert_eq!(
+            type_keys(&mut listener, "a a a a b"),
+            vec![ChainAction::ToggleForm]
+        );
+        assert_eq!(
+            type_keys(&mut listener, "a b a a b"),
+            vec![ChainAction::ToggleForm]
+        );
+    }
+
+    #[test]
+    fn buffer_is_cleared_after_a_match() {
+        let mut listener = listener(&[("a b a", ChainAction::ToggleForm)]);
+
+        // the trailing `a` of the first match does not start the second one
+        assert_eq!(
+            type_keys(&mut listener, "a b a b a"),
+            vec![ChainAction::ToggleForm]
+        );
+        assert_eq!(
+            type_keys(&mut listener, "a b a a b a"),
+            vec![ChainAction::ToggleForm, ChainAction::ToggleForm]
+        );
+    }
+
+    #[test]
+    fn other_keys_break_the_chain() {
+        let mut listener = listener(&[("a b", ChainAction::ToggleForm)]);
+
+        assert_eq!(type_keys(&mut listener, "a x b"), vec![]);
+        assert_eq!(type_keys(&mut listener, "a Enter b"), vec![]);
+    }
+
+    #[test]
+    fn respects_modifiers_and_special_keys() {
+        let mut listener = listener(&[("ctrl+alt+F12 a", ChainAction::ToggleForm)]);
+
+        assert_eq!(type_keys(&mut listener, "F12 a"), vec![]);
+        assert_eq!(type_keys(&mut listener, "ctrl+F12 a"), vec![]);
+        assert_eq!(type_keys(&mut listener, "ctrl+alt+F12 ctrl+a"), vec![]);
+        // pressing the modifier keys themselves does not count as a key of the chain
+        assert_eq!(
+            type_keys(&mut listener, "Control ctrl+alt+F12 a"),
+            vec![ChainAction::ToggleForm]
+        );
+    }
+
+    #[test]
+    fn ignores_case_and_shift_of_characters() {
+        let mut listener = listener(&[("A d M", ChainAction::ToggleForm)]);
+
+        assert_eq!(
+            type_keys(&mut listener, "shift+a d shift+m"),
+            vec![ChainAction::ToggleForm]
+        );
+        assert_eq!(
+            type_keys(&mut listener, "a shift+d m"),
+            vec![ChainAction::ToggleForm]
+        );
+    }
+
+    #[test]
+    fn matches_shifted_symbols() {
+        let mut listener = listener(&[("p@ss", ChainAction::ToggleForm)]);
+
+        assert_eq!(type_keys(&mut listener, "p 2 s s"), vec![]);
+        assert_eq!(
+            type_keys(&mut listener, "p shift+2 s s"),
+            vec![ChainAction::ToggleForm]
+        );
+    }
+
+    #[test]
+    fn requires_shift_when_the_chain_spells_it_out() {
+        let mut listener = listener(&[("shift+a b", ChainAction::ToggleForm)]);
+
+        assert_eq!(type_keys(&mut listener, "a b"), vec![]);
+        assert_eq!(
+            type_keys(&mut listener, "shift+a b"),
+            vec![ChainAction::ToggleForm]
+        );
+    }
+
+    #[test]
+    fn matches_non_latin_chains_by_character_count() {
+        let mut listener = listener(&[("ключ", ChainAction::ToggleForm)]);
+
+        assert_eq!(type_keys(&mut listener, "к л ю"), vec![]);
+        assert_eq!(type_keys(&mut listener, "ч"), vec![ChainAction::ToggleForm]);
+    }
+
+    #[test]
+    fn matches_latin_chains_typed_on_another_layout() {
+        let mut listener = listener(&[("ab", ChainAction::ToggleForm)]);
+        let now = Instant::now();
+
+        let press = |listener: &mut KeyListener, text: &str, code: Code| {
+            listener.key_pressed(
+                Key::Character(text.into()),
+                Key::Character(text.into()),
+                Physical::Code(code),
+                Modifiers::empty(),
+                now,
+            )
+        };
+        assert_eq!(press(&mut listener, "ф", Code::KeyA), None);
+        assert_eq!(
+            press(&mut listener, "и", Code::KeyB),
+            Some(ChainAction::ToggleForm)
+        );
+    }
+
+    #[test]
+    fn resets_the_buffer_after_the_timeout() {
+        let mut listener = listener(&[("a b", ChainAction::ToggleForm)]);
+        let start = Instant::now();
+        let none = Modifiers::empty();
+
+        press(&mut listener, "a", none, start);
+        assert_eq!(
+            press(
+                &mut listener,
+                "b",
+                none,
+                start + TIMEOUT + Duration::from_millis(1)
+            ),
+            None
+        );
+
+        press(&mut listener, "a", none, start);
+        assert_eq!(
+            press(&mut listener, "b", none, start + TIMEOUT),
+            Some(ChainAction::ToggleForm)
+        );
+    }
+
+    #[test]
+    fn zero_timeout_never_resets_the_buffer() {
+        let mut listener = KeyListener::new(
+            vec![("a b".to_string(), ChainAction::ToggleForm)],
+            Duration::ZERO,
+        );
+        let start = Instant::now();
+        let none = Modifiers::empty();
+
+        press(&mut listener, "a", none, start);
+        assert_eq!(
+            press(&mut listener, "b", none, start + Duration::from_secs(3600)),
+            Some(ChainAction::ToggleForm)
+        );
+    }
+
+    #[test]
+    fn does_not_buffer_without_valid_chains() {
+        let mut listener = KeyListener::new(
+            vec![("hyper+a".to_string(), ChainAction::ToggleForm)],
+            Duration::ZERO,
+        );
+
+        assert_eq!(type_keys(&mut listener, "h y p e r a"), vec![]);
+        assert!(listener.buffer.is_empty());
+    }
+
+    #[test]
+    fn triggers_the_action_of_each_chain() {
+        let mut listener = listener(&[
+            ("a d m i n", ChainAction::ToggleForm),
+            ("ctrl+l", ChainAction::AutoLogin),
+            ("d i a g", ChainAction::Diagnostics),
+        ]);
+
+        assert_eq!(
+            type_keys(&mut listener, "a d m i n ctrl+l x d i a g"),
+            vec![
+                ChainAction::ToggleForm,
+                ChainAction::AutoLogin,
+                ChainAction::Diagnostics
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_hashed_chains() {
+        let hashed = KeyChain::from_str("ctrl+alt+F12 A d m i n @")
+            .unwrap()
+            .hashed(NonZeroU32::MIN)
+            .unwrap()
+            .to_string();
+        assert!(!hashed.contains("admin"));
+        let mut listener = listener(&[(hashed.as_str(), ChainAction::ToggleForm)]);
+
+        assert_eq!(
+            type_keys(&mut listener, "ctrl+alt+F12 a d m i x shift+2"),
+            vec![]
+        );
+        assert_eq!(
+            type_keys(&mut listener, "ctrl+alt+F12 shift+a d m i n shift+2"),
+            vec![ChainAction::ToggleForm]
+        );
+        assert_eq!(
+            type_keys(&mut listener, "ctrl+alt+F12 a d m i n shift+2"),
+            vec![ChainAction::ToggleForm]
+        );
+    }
+
+    #[test]
+    fn parses_and_rejects_chains() {
+        assert_eq!(
+            KeyChain::from_str("Ctrl+Alt+f12  Return a")
+                .unwrap()
+                .to_string(),
+            "ctrl+alt+f12 enter a"
+        );
+        assert_eq!(KeyChain::from_str("ctrl++").unwrap().to_string(), "ctrl++");
+        assert_eq!(KeyChain::from_str("+").unwrap().to_string(), "+");
+        assert_eq!(KeyChain::from_str("Enter").unwrap().len(), 5);
+        assert_eq!(KeyChain::from_str("ctrl+Enter").unwrap().len(), 1);
+        assert_eq!(KeyChain::from_str("ключ").unwrap().len(), 4);
+
+        assert!(KeyChain::from_str("").is_err());
+        assert!(KeyChain::from_str("hyper+a").is_err());
+        assert!(KeyChain::from_str("ctrl+F13").is_err());
+        let digest = "00".repeat(32);
+        assert!(KeyChain::from_str(&format!("pbkdf2:10:3:00:{digest}")).is_ok());
+        assert!(KeyChain::from_str(&format!("pbkdf2:0:3:00:{digest}")).is_err());
+        assert!(KeyChain::from_str(&format!("pbkdf2:10:0:00:{digest}")).is_err());
+        assert!(KeyChain::from_str(&format!("pbkdf2:10:3:zz:{digest}")).is_err());
+        assert!(KeyChain::from_str("pbkdf2:10:3:00:00").is_err());
+        assert!(KeyChain::from_str(&format!("sha256:3:00:{digest}")).is_err());
+    }
+}
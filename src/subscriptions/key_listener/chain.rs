@@ -0,0 +1,360 @@
+use std::{fmt, num::NonZeroU32, str::FromStr};
+
+use iced::keyboard::{Modifiers, key::Named};
+use ring::{
+    pbkdf2::{self, PBKDF2_HMAC_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+const HASH_PREFIX: &str = "pbkdf2:";
+const SALT_LEN: usize = 16;
+const DIGEST_LEN: usize = 32;
+
+const MODIFIERS: [(&str, Modifiers); 4] = [
+    ("ctrl", Modifiers::CTRL),
+    ("alt", Modifiers::ALT),
+    ("shift", Modifiers::SHIFT),
+    ("super", Modifiers::LOGO),
+];
+
+const NAMED_KEYS: [(&str, Named); 27] = [
+    ("enter", Named::Enter),
+    ("escape", Named::Escape),
+    ("tab", Named::Tab),
+    ("space", Named::Space),
+    ("backspace", Named::Backspace),
+    ("delete", Named::Delete),
+    ("insert", Named::Insert),
+    ("home", Named::Home),
+    ("end", Named::End),
+    ("pageup", Named::PageUp),
+    ("pagedown", Named::PageDown),
+    ("up", Named::ArrowUp),
+    ("down", Named::ArrowDown),
+    ("left", Named::ArrowLeft),
+    ("right", Named::ArrowRight),
+    ("f1", Named::F1),
+    ("f2", Named::F2),
+    ("f3", Named::F3),
+    ("f4", Named::F4),
+    ("f5", Named::F5),
+    ("f6", Named::F6),
+    ("f7", Named::F7),
+    ("f8", Named::F8),
+    ("f9", Named::F9),
+    ("f10", Named::F10),
+    ("f11", Named::F11),
+    ("f12", Named::F12),
+];
+
+/// A single key press of a chain, e.g. `ctrl+alt+F12` or `a`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyCombo {
+    modifiers: Modifiers,
+    key: ChainKey,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainKey {
+    Char(char),
+    Named(Named),
+}
+
+impl ChainKey {
+    pub fn from_char(c: char) -> Self {
+        ChainKey::Char(c.to_lowercase().next().unwrap_or(c))
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').collect();
+        // a trailing `+` is the plus key itself, e.g. `ctrl++`
+        if s == "+" || s.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let (key, modifier_names) = parts
+            .split_last()
+            .ok_or_else(|| format!("empty key in {s}"))?;
+
+        let mut modifiers = Modifiers::empty();
+        for name in modifier_names {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" | "control" => Modifiers::CTRL,
+                "alt" => Modifiers::ALT,
+                "shift" => Modifiers::SHIFT,
+                "super" | "logo" | "meta" => Modifiers::LOGO,
+                _ => return Err(format!("unknown modifier {name} in {s}")),
+            };
+        }
+
+        let mut chars = key.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) => ChainKey::from_char(c),
+            _ => {
+                ChainKey::Named(named_key(key).ok_or_else(|| format!("unknown key {key} in {s}"))?)
+            }
+        };
+        Ok(Self { modifiers, key })
+    }
+}
+
+/// A pressed key, both as the character of the active layout and as its latin equivalent
+/// so chains typed on a different layout still match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+    actual: KeyCombo,
+    latin: KeyCombo,
+}
+
+impl KeyPress {
+    pub fn new(modifiers: Modifiers, key: ChainKey, latin: Option<ChainKey>) -> Self {
+        let actual = KeyCombo { modifiers, key };
+        let latin = latin.map_or_else(|| actual.clone(), |key| KeyCombo { modifiers, key });
+        Self { actual, latin }
+    }
+
+    /// Shift is part of the typed character (`A`, `@`), so it only counts for characters
+    /// when the chain spells it out, e.g. `shift+a`.
+    fn is(&self, combo: &KeyCombo) -> bool {
+        let ignore_shift =
+            matches!(combo.key, ChainKey::Char(_)) && !combo.modifiers.contains(Modifiers::SHIFT);
+        [&self.actual, &self.latin].into_iter().any(|pressed| {
+            if ignore_shift {
+                pressed.without_char_shift() == *combo
+            } else {
+                pressed == combo
+            }
+        })
+    }
+}
+
+impl KeyCombo {
+    fn without_char_shift(&self) -> KeyCombo {
+        let mut combo = self.clone();
+        if matches!(combo.key, ChainKey::Char(_)) {
+            combo.modifiers.remove(Modifiers::SHIFT);
+        }
+        combo
+    }
+}
+
+/// Canonical form used for hashing, e.g. `ctrl+alt+f12`.
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, modifier) in MODIFIERS {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        match &self.key {
+            ChainKey::Char(c) => write!(f, "{c}"),
+            ChainKey::Named(named) => {
+                let name = NAMED_KEYS
+                    .iter()
+                    .find(|(_, key)| key == named)
+                    .map_or("unknown", |(name, _)| name);
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// Key sequence, e.g. `ctrl+alt+F12 a d m i n`.
+///
+/// Combos are separated by whitespace. A chain without whitespace or `+` is read as one key
+/// per character, so plain strings like `chain` keep working. A single named key therefore
+/// needs a modifier, e.g. `ctrl+F12`.
+///
+/// A chain can also be given as `pbkdf2:<iterations>:<length>:<salt>:<digest>` so the
+/// secret is not readable from the config. The digest is the PBKDF2-HMAC-SHA256 of the
+/// canonical chain with the salt, both hex encoded. `contest-greeter --hash-chain <CHAIN>`
+/// prints it. Hashed chains ignore `shift` on characters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyChain {
+    Plain(Vec<KeyCombo>),
+    Hashed {
+        iterations: NonZeroU32,
+        len: usize,
+        salt: Vec<u8>,
+        digest: Vec<u8>,
+    },
+}
+
+impl KeyChain {
+    /// PBKDF2 iterations used by `--hash-chain`. A check takes a few dozen milliseconds,
+    /// which is fine once per key press but makes guessing short chains slow.
+    pub const DEFAULT_ITERATIONS: NonZeroU32 = NonZeroU32::new(100_000).unwrap();
+
+    /// Number of key combos in the chain.
+    pub fn len(&self) -> usize {
+        match self {
+            KeyChain::Plain(combos) => combos.len(),
+            KeyChain::Hashed { len, .. } => *len,
+        }
+    }
+
+    /// Whether `keys` is exactly this chain. Hashed chains only match when all keys are
+    /// compared on the active layout or all as latin keys.
+    pub fn matches<'a, I>(&self, keys: I) -> bool
+    where
+        I: ExactSizeIterator<Item = &'a KeyPress> + Clone,
+    {
+        match self {
+            KeyChain::Plain(combos) => {
+                keys.len() == combos.len() && keys.zip(combos).all(|(key, combo)| key.is(combo))
+            }
+            KeyChain::Hashed {
+                iterations,
+                len,
+                salt,
+                digest,
+            } => {
+                if keys.len() != *len {
+                    return false;
+                }
+                let actual = canonical(keys.clone().map(|key| key.actual.without_char_shift()));
+                let latin = canonical(keys.map(|key| key.latin.without_char_shift()));
+                let verify = |chain: &str| {
+                    pbkdf2::verify(
+                        PBKDF2_HMAC_SHA256,
+                        *iterations,
+                        salt,
+                        chain.as_bytes(),
+                        digest,
+                    )
+                    .is_ok()
+                };
+                // only derive the latin variant when it differs, the derivation is slow
+                verify(&actual) || (latin != actual && verify(&latin))
+            }
+        }
+    }
+
+    /// Hashes a plain chain with a random salt.
+    pub fn hashed(&self, iterations: NonZeroU32) -> Result<Self, String> {
+        let KeyChain::Plain(combos) = self else {
+            return Err("key chain is already hashed".to_string());
+        };
+        let mut salt = vec![0; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| "failed to generate a salt".to_string())?;
+        let chain = canonical(combos.iter().map(KeyCombo::without_char_shift));
+        let mut digest = vec![0; DIGEST_LEN];
+        pbkdf2::derive(
+            PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            chain.as_bytes(),
+            &mut digest,
+        );
+        Ok(KeyChain::Hashed {
+            iterations,
+            len: combos.len(),
+            salt,
+            digest,
+        })
+    }
+}
+
+impl FromStr for KeyChain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(hashed) = s.strip_prefix(HASH_PREFIX) {
+            return parse_hashed(hashed);
+        }
+        // would otherwise be read as a plain chain that anyone can type
+        if s.starts_with("sha256:") {
+            return Err(
+                "sha256 key chains are not supported, hash it again with --hash-chain".to_string(),
+            );
+        }
+
+        let is_plain_string = !s.contains(char::is_whitespace) && !s.contains('+');
+        let combos = if is_plain_string {
+            s.chars()
+                .map(|c| KeyCombo::from_str(&c.to_string()))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            s.split_whitespace()
+                .map(KeyCombo::from_str)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        if combos.is_empty() {
+            return Err("key chain is empty".to_string());
+        }
+        Ok(KeyChain::Plain(combos))
+    }
+}
+
+impl fmt::Display for KeyChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyChain::Plain(combos) => f.write_str(&canonical(combos.iter().cloned())),
+            KeyChain::Hashed {
+                iterations,
+                len,
+                salt,
+                digest,
+            } => write!(
+                f,
+                "{HASH_PREFIX}{iterations}:{len}:{}:{}",
+                hex::encode(salt),
+                hex::encode(digest)
+            ),
+        }
+    }
+}
+
+fn parse_hashed(s: &str) -> Result<KeyChain, String> {
+    let [iterations, len, salt, digest] = s.split(':').collect::<Vec<_>>()[..] else {
+        return Err(format!(
+            "hashed key chain must look like {HASH_PREFIX}<iterations>:<length>:<salt>:<digest>"
+        ));
+    };
+    let iterations = iterations
+        .parse()
+        .map_err(|_| format!("invalid iteration count {iterations}"))?;
+    let len = len
+        .parse()
+        .ok()
+        .filter(|len| *len > 0)
+        .ok_or_else(|| format!("invalid key chain length {len}"))?;
+    let salt = hex::decode(salt).map_err(|e| format!("invalid salt: {e}"))?;
+    let digest = hex::decode(digest).map_err(|e| format!("invalid digest: {e}"))?;
+    if digest.len() != DIGEST_LEN {
+        return Err(format!("digest must be {DIGEST_LEN} bytes"));
+    }
+    Ok(KeyChain::Hashed {
+        iterations,
+        len,
+        salt,
+        digest,
+    })
+}
+
+fn canonical(keys: impl Iterator<Item = KeyCombo>) -> String {
+    keys.map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn named_key(name: &str) -> Option<Named> {
+    let name = match name.to_lowercase().as_str() {
+        "return" => "enter".to_string(),
+        "esc" => "escape".to_string(),
+        "del" => "delete".to_string(),
+        "ins" => "insert".to_string(),
+        name => name.to_string(),
+    };
+    NAMED_KEYS
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| *key)
+}
@@ -0,0 +1,60 @@
+use std::net::IpAddr;
+
+use local_ip_address::local_ip;
+
+use crate::{conf::Conf, host};
+
+/// Values for the `{hostname}`, `{ip}`, `{mac}` and `{team_id}` placeholders that can be
+/// used in `background_source`, `background_label` and `url`.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars {
+    pub hostname: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub mac: Option<String>,
+    pub team_id: Option<String>,
+}
+
+impl TemplateVars {
+    pub fn new(config: &Conf) -> Self {
+        let mut vars = Self {
+            hostname: host::hostname(),
+            team_id: config.team_id.clone(),
+            ..Default::default()
+        };
+        vars.set_ip(local_ip().ok());
+        vars
+    }
+
+    /// Sets `{ip}` and `{mac}` to the MAC address of the interface with that ip, or of the
+    /// first physical interface.
+    pub fn set_ip(&mut self, ip: Option<IpAddr>) {
+        self.ip = ip;
+        self.mac = ip
+            .and_then(host::mac_address_of_ip)
+            .or_else(|| host::mac_addresses().into_iter().next());
+    }
+
+    /// Replaces all known placeholders in `template`. Unknown values expand to an empty string.
+    pub fn expand(&self, template: &str) -> String {
+        let ip = self.ip.map(|ip| ip.to_string());
+        [
+            ("{hostname}", self.hostname.as_deref()),
+            ("{ip}", ip.as_deref()),
+            ("{mac}", self.mac.as_deref()),
+            ("{team_id}", self.team_id.as_deref()),
+        ]
+        .into_iter()
+        .fold(template.to_string(), |text, (placeholder, value)| {
+            text.replace(placeholder, value.unwrap_or_default())
+        })
+    }
+
+    /// Returns a copy of `config` with the templated fields expanded.
+    pub fn expand_conf(&self, config: &Conf) -> Conf {
+        let mut config = config.clone();
+        config.background_source = config.background_source.map(|s| self.expand(&s));
+        config.background_label = config.background_label.map(|s| self.expand(&s));
+        config.url = config.url.map(|s| self.expand(&s));
+        config
+    }
+}
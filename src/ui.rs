@@ -1,45 +1,77 @@
+pub mod announcements;
 pub mod background;
+pub mod broadcast;
 pub mod countdown;
+pub mod diagnostics;
 pub mod form;
 pub mod ip_label;
+pub mod remote_status;
+
+use std::time::Duration;
 
 use anyhow::Result;
-use iced::{Element, Subscription, Task, Theme, widget::Stack};
+use chrono::{Local, TimeDelta};
+use contest_greeter_dbus::GreeterState;
+use iced::{Element, Size, Subscription, Task, Theme, widget::Stack, window};
+use log::{debug, info};
 
 use crate::{
+    cli::Cli,
     conf::Conf,
-    ipc::greeter_client::{GreeterClient, GreeterClientMessage},
+    ipc::{
+        greeter_client::{GreeterClient, GreeterClientMessage},
+        login_throttle::LoginThrottle,
+    },
     subscriptions::{
         api_poller::{ApiPoller, ApiPollerMessage},
-        dbus::{DbusMessage, dbus_service_subscription},
+        config_watcher::{ConfigWatcher, ConfigWatcherMessage},
+        dbus::{
+            AccessPolicy, DbusMessage, DbusSettings, StatePublisher, dbus_service_subscription,
+        },
         key_listener::{KeyListener, KeyListenerMessage},
     },
+    template::TemplateVars,
     ui::{
+        announcements::{Announcements, AnnouncementsMessage},
         background::{Background, BackgroundMessage},
+        broadcast::{Broadcast, BroadcastMessage},
         countdown::{Countdown, CountdownMessage},
+        diagnostics::Diagnostics,
         form::{Form, FormMessage},
         ip_label::{IpLabel, IpLabelMessage},
+        remote_status::RemoteStatus,
     },
 };
 
 pub struct Greeter {
     // ui
+    announcements: Announcements,
     background: Background,
+    broadcast: Broadcast,
     form: Form,
     countdown: Countdown,
+    diagnostics: Diagnostics,
     ip_label: IpLabel,
+    remote_status: RemoteStatus,
 
     // subscriptions
     key_listener: KeyListener,
     api_poller: ApiPoller,
+    config_watcher: ConfigWatcher,
 
     greeter_client: GreeterClient,
+    state_publisher: Option<StatePublisher>,
 
     config: Conf,
+    template_vars: TemplateVars,
+    /// Set by `--demo-start-in`, start times from the contest API are ignored.
+    demo_start: bool,
 }
 
 pub enum Message {
+    Announcements(AnnouncementsMessage),
     Background(BackgroundMessage),
+    Broadcast(BroadcastMessage),
     Form(FormMessage),
     IpLabel(IpLabelMessage),
     KeyListener(KeyListenerMessage),
@@ -47,50 +79,93 @@ pub enum Message {
     ApiPoller(ApiPollerMessage),
     Countdown(CountdownMessage),
     Dbus(DbusMessage),
+    ConfigWatcher(ConfigWatcherMessage),
 }
 
 impl Greeter {
-    pub fn new(config: Conf) -> (Self, Task<Message>) {
+    pub fn new(config: Conf, cli: &Cli) -> (Self, Task<Message>) {
+        let template_vars = TemplateVars::new(&config);
+        let expanded = template_vars.expand_conf(&config);
+
         let (background, background_task) = Background::new(
-            config.background_source.clone(),
-            config.background_label.clone(),
-            config.background_label_color.clone(),
+            expanded.background_source.clone(),
+            expanded.background_label.clone(),
+            expanded.background_label_color.clone(),
+        );
+        let mut form = Form::new(Duration::from_secs(config.form_timeout_secs));
+        form.set_usernames(
+            config.form_username.clone(),
+            config.form_username_locked,
+            config.form_usernames.clone(),
         );
-        let form = Form::new();
+        form.set_keyboard_layouts(config.keyboard_layouts.clone());
         let countdown = Countdown::default();
         let (ip_label, ip_label_task) = IpLabel::new();
 
-        let key_listener = KeyListener::new(config.chain.clone());
-        let (api_poller, api_poller_task) = ApiPoller::new(config.url.clone());
+        let key_listener = KeyListener::new(
+            config.key_chains(),
+            Duration::from_millis(config.chain_timeout_ms),
+        );
+        let (api_poller, api_poller_task) = ApiPoller::new(expanded.url.clone());
+        let config_watcher = ConfigWatcher::new(cli.config_path(), cli.config_dir());
 
-        let greeter_client = GreeterClient::new(
+        let mut greeter_client = GreeterClient::new(
             config.session.clone(),
             config.username.clone(),
             config.password.clone(),
+            cli.dry_run,
+            LoginThrottle::new(
+                config.login_max_attempts,
+                Duration::from_secs(config.login_delay_secs),
+                Duration::from_secs(config.login_lockout_secs),
+            ),
+        );
+        greeter_client.set_keyboard_layout(config.keyboard_layouts.first().cloned());
+        form.set_sessions(
+            greeter_client.session_names(),
+            greeter_client.resolved_session().map(str::to_string),
         );
 
+        let mut tasks = vec![
+            background_task.map(Message::Background),
+            api_poller_task.map(Message::ApiPoller),
+            ip_label_task.map(Message::IpLabel),
+        ];
+        if let Some(secs) = cli.demo_start_in {
+            let start_time = Local::now() + TimeDelta::seconds(secs as i64);
+            tasks.push(Task::done(
+                CountdownMessage::SetStartTime(start_time).into(),
+            ));
+        }
+
         (
             Self {
+                announcements: Announcements::default(),
                 background,
+                broadcast: Broadcast::default(),
                 form,
                 countdown,
+                diagnostics: Diagnostics::default(),
                 ip_label,
+                remote_status: RemoteStatus::default(),
                 key_listener,
                 api_poller,
+                config_watcher,
                 greeter_client,
+                state_publisher: None,
                 config,
+                template_vars,
+                demo_start: cli.demo_start_in.is_some(),
             },
-            Task::batch(vec![
-                background_task.map(Message::Background),
-                api_poller_task.map(Message::ApiPoller),
-                ip_label_task.map(Message::IpLabel),
-            ]),
+            Task::batch(tasks),
         )
     }
 
     pub fn view(&self) -> Element<'_, Message> {
         let (background, background_label) = self.background.view();
         let (countdown_label, countdown_indicator_fn) = self.countdown.view();
+        let announcements = self.announcements.view();
+        let broadcast = self.broadcast.view();
         let form_element = self.form.view();
         let ip_label = self.ip_label.view();
 
@@ -109,6 +184,22 @@ impl Greeter {
             layers.push(countdown_indicator_fn(form_element.is_some()).map(Message::Countdown));
         }
 
+        if let Some(a) = announcements {
+            layers.push(a.map(Message::Announcements));
+        }
+
+        if let Some(b) = broadcast {
+            layers.push(b.map(Message::Broadcast));
+        }
+
+        if let Some(remote_status) = self.remote_status.view(form_element.is_some()) {
+            layers.push(remote_status);
+        }
+
+        if let Some(diagnostics) = self.diagnostics.view(|| self.diagnostics_rows()) {
+            layers.push(diagnostics);
+        }
+
         if let Some(f) = form_element {
             layers.push(f.map(Message::Form));
             layers.push(ip_label.map(Message::IpLabel));
@@ -118,34 +209,162 @@ impl Greeter {
     }
 
     pub fn update(&mut self, msg: Message) -> Task<Message> {
+        let task = self.handle(msg);
+        if let Some(state_publisher) = &self.state_publisher {
+            state_publisher.publish(self.state());
+        }
+        task
+    }
+
+    fn diagnostics_rows(&self) -> Vec<(&'static str, String)> {
+        let state = self.state();
+        let vars = &self.template_vars;
+        let dbus = if !self.config.enable_dbus {
+            "disabled".to_string()
+        } else if let Some(error) = self.remote_status.error() {
+            format!("unavailable: {error}")
+        } else {
+            "available".to_string()
+        };
+        vec![
+            ("Version", state.version),
+            ("Hostname", vars.hostname.clone().unwrap_or_default()),
+            ("IP", vars.ip.map(|ip| ip.to_string()).unwrap_or_default()),
+            ("MAC", vars.mac.clone().unwrap_or_default()),
+            ("Team", vars.team_id.clone().unwrap_or_default()),
+            ("Session", state.session),
+            ("Countdown", state.countdown_state),
+            ("Start time", state.start_time),
+            ("Wallpaper", state.wallpaper_status),
+            ("Last login error", state.last_login_error),
+            (
+                "API url",
+                self.template_vars
+                    .expand_conf(&self.config)
+                    .url
+                    .unwrap_or_default(),
+            ),
+            ("D-Bus", dbus),
+        ]
+    }
+
+    fn state(&self) -> GreeterState {
+        GreeterState {
+            start_time: self
+                .countdown
+                .start_time()
+                .map(|start_time| start_time.to_rfc3339())
+                .unwrap_or_default(),
+            countdown_state: self.countdown.state().to_string(),
+            wallpaper_status: self.background.status().to_string(),
+            last_login_error: self
+                .greeter_client
+                .last_error()
+                .unwrap_or_default()
+                .to_string(),
+            session: self
+                .greeter_client
+                .resolved_session()
+                .unwrap_or_default()
+                .to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    fn handle(&mut self, msg: Message) -> Task<Message> {
         match msg {
+            Message::Announcements(announcements_message) => self
+                .announcements
+                .update(announcements_message)
+                .map(Message::Announcements),
             Message::Background(background_message) => self
                 .background
                 .update(background_message)
                 .map(Message::Background),
+            Message::Broadcast(broadcast_message) => self
+                .broadcast
+                .update(broadcast_message)
+                .map(Message::Broadcast),
             Message::Form(form_message) => {
-                if let FormMessage::LoginWithCredentials(username, password) = form_message {
+                if let FormMessage::LoginWithCredentials {
+                    username,
+                    password,
+                    session,
+                    keyboard_layout,
+                } = form_message
+                {
                     return Task::done(
-                        GreeterClientMessage::LoginWithCredentials(username, password).into(),
+                        GreeterClientMessage::ManualLogin {
+                            username,
+                            password,
+                            session,
+                            keyboard_layout,
+                        }
+                        .into(),
                     );
                 }
+                if let FormMessage::KeyboardLayoutSelected(keyboard_layout) = &form_message {
+                    // automatic logins use the layout selected last
+                    self.greeter_client
+                        .set_keyboard_layout(Some(keyboard_layout.clone()));
+                }
                 self.form.update(form_message).map(Message::Form)
             }
-            Message::KeyListener(msg) => {
-                if let KeyListenerMessage::ChainTriggered = msg {
-                    return Task::done(FormMessage::ToggleVisible.into());
+            Message::KeyListener(msg) => match msg {
+                KeyListenerMessage::ToggleForm => Task::done(FormMessage::ToggleVisible.into()),
+                KeyListenerMessage::AutoLogin => Task::done(
+                    GreeterClientMessage::Login {
+                        reply: None,
+                        wait: false,
+                    }
+                    .into(),
+                ),
+                KeyListenerMessage::ToggleDiagnostics => {
+                    self.diagnostics.toggle();
+                    Task::none()
                 }
-                self.key_listener.update(msg).map(Message::KeyListener)
-            }
+                KeyListenerMessage::ReloadConfig => Task::done(ConfigWatcherMessage::Reload.into()),
+                KeyListenerMessage::RefetchWallpaper => {
+                    info!("re-fetching background");
+                    let source = self
+                        .template_vars
+                        .expand_conf(&self.config)
+                        .background_source;
+                    Task::done(BackgroundMessage::SetSource(source).into())
+                }
+                KeyListenerMessage::Event(_) => {
+                    self.key_listener.update(msg).map(Message::KeyListener)
+                }
+            },
             Message::GreeterClient(msg) => match msg {
                 GreeterClientMessage::LoginError(error) => {
                     Task::done(FormMessage::SetError(error).into())
                 }
+                GreeterClientMessage::LoginBlocked(until) => {
+                    Task::done(FormMessage::BlockUntil(until).into())
+                }
                 _ => self.greeter_client.update(msg).map(Message::GreeterClient),
             },
             Message::ApiPoller(api_poller_message) => {
-                if let ApiPollerMessage::SetStartime(starttime) = api_poller_message {
-                    return Task::done(CountdownMessage::SetStartTime(starttime).into());
+                match api_poller_message {
+                    ApiPollerMessage::SetStartime(_) if self.demo_start => {
+                        debug!("ignoring the start time from the API, --demo-start-in is set");
+                        return Task::none();
+                    }
+                    ApiPollerMessage::SetStartime(starttime) => {
+                        return Task::done(CountdownMessage::SetStartTime(starttime).into());
+                    }
+                    ApiPollerMessage::SetNotice(notice) => {
+                        return Task::done(
+                            notice
+                                .map_or(BroadcastMessage::Dismiss, BroadcastMessage::Show)
+                                .into(),
+                        );
+                    }
+                    ApiPollerMessage::SetAnnouncements(announcements) => {
+                        return Task::done(AnnouncementsMessage::Set(announcements).into());
+                    }
+                    _ => {}
                 }
                 self.api_poller
                     .update(api_poller_message)
@@ -153,36 +372,200 @@ impl Greeter {
             }
             Message::Countdown(countdown_message) => {
                 if let CountdownMessage::Start = countdown_message {
-                    return Task::done(GreeterClientMessage::Login.into());
+                    return Task::done(
+                        GreeterClientMessage::Login {
+                            reply: None,
+                            wait: false,
+                        }
+                        .into(),
+                    );
                 }
                 self.countdown
                     .update(countdown_message)
                     .map(Message::Countdown)
             }
             Message::Dbus(dbus_message) => match dbus_message {
+                DbusMessage::Connected(state_publisher) => {
+                    self.state_publisher = Some(state_publisher);
+                    self.remote_status.set_available();
+                    Task::none()
+                }
+                DbusMessage::Unavailable(reason) => {
+                    self.state_publisher = None;
+                    self.remote_status.set_unavailable(reason);
+                    Task::none()
+                }
                 DbusMessage::SetWallpaper(source) => {
                     Task::done(BackgroundMessage::SetSource(Some(source)).into())
                 }
-                DbusMessage::Login => Task::done(GreeterClientMessage::Login.into()),
+                DbusMessage::Login { wait, reply } => Task::done(
+                    GreeterClientMessage::Login {
+                        reply: Some(reply),
+                        wait,
+                    }
+                    .into(),
+                ),
                 DbusMessage::SetApiUrl(url) => {
                     Task::done(ApiPollerMessage::SetUrl(Some(url)).into())
                 }
+                DbusMessage::SetStartTime(start_time) => {
+                    Task::done(CountdownMessage::SetStartTime(start_time).into())
+                }
+                DbusMessage::ClearStartTime => Task::done(CountdownMessage::ClearStartTime.into()),
+                DbusMessage::SetBackgroundLabel { text, color } => {
+                    Task::done(BackgroundMessage::UpdateLabel(text, color).into())
+                }
+                DbusMessage::ShowMessage(notice) => {
+                    Task::done(BroadcastMessage::Show(notice).into())
+                }
+                DbusMessage::DismissMessage => Task::done(BroadcastMessage::Dismiss.into()),
+                DbusMessage::ToggleLoginForm => Task::done(FormMessage::ToggleVisible.into()),
+                DbusMessage::ReloadConfig => Task::done(ConfigWatcherMessage::Reload.into()),
             },
             Message::IpLabel(ip_label_message) => {
-                self.ip_label.update(ip_label_message).map(Message::IpLabel)
+                let mut task = Task::none();
+                if let IpLabelMessage::SetIp(ip) = &ip_label_message
+                    && *ip != self.template_vars.ip
+                {
+                    let old = self.template_vars.expand_conf(&self.config);
+                    self.template_vars.set_ip(*ip);
+                    let new = self.template_vars.expand_conf(&self.config);
+                    task = self.apply_changes(&old, &new);
+                }
+                Task::batch(vec![
+                    task,
+                    self.ip_label.update(ip_label_message).map(Message::IpLabel),
+                ])
+            }
+            Message::ConfigWatcher(config_watcher_message) => {
+                if let ConfigWatcherMessage::ConfigChanged(config) = config_watcher_message {
+                    let old = self.template_vars.expand_conf(&self.config);
+                    self.template_vars.team_id = config.team_id.clone();
+                    let new = self.template_vars.expand_conf(&config);
+                    self.config = *config;
+                    return self.apply_changes(&old, &new);
+                }
+                self.config_watcher
+                    .update(config_watcher_message)
+                    .map(Message::ConfigWatcher)
             }
         }
     }
 
+    /// Applies the fields of the expanded `new` config that differ from `old`.
+    /// The log level is only read at startup.
+    fn apply_changes(&mut self, old: &Conf, new: &Conf) -> Task<Message> {
+        let mut tasks = vec![];
+
+        if new.background_source != old.background_source {
+            info!("background source changed, reloading background");
+            tasks.push(Task::done(
+                BackgroundMessage::SetSource(new.background_source.clone()).into(),
+            ));
+        }
+        if new.background_label != old.background_label
+            || new.background_label_color != old.background_label_color
+        {
+            tasks.push(Task::done(
+                BackgroundMessage::UpdateLabel(
+                    new.background_label.clone(),
+                    new.background_label_color.clone(),
+                )
+                .into(),
+            ));
+        }
+        if new.url != old.url {
+            info!("api url changed, fetching start time");
+            tasks.push(Task::done(ApiPollerMessage::SetUrl(new.url.clone()).into()));
+            tasks.push(Task::done(ApiPollerMessage::FetchStartTime.into()));
+        }
+        if !new.enable_dbus {
+            self.state_publisher = None;
+            self.remote_status.set_available();
+        }
+        if new.chain != old.chain
+            || new.chains != old.chains
+            || new.chain_timeout_ms != old.chain_timeout_ms
+        {
+            self.key_listener = KeyListener::new(
+                new.key_chains(),
+                Duration::from_millis(new.chain_timeout_ms),
+            );
+        }
+        if new.form_timeout_secs != old.form_timeout_secs {
+            self.form
+                .set_inactivity_timeout(Duration::from_secs(new.form_timeout_secs));
+        }
+        if new.form_username != old.form_username
+            || new.form_username_locked != old.form_username_locked
+            || new.form_usernames != old.form_usernames
+        {
+            self.form.set_usernames(
+                new.form_username.clone(),
+                new.form_username_locked,
+                new.form_usernames.clone(),
+            );
+        }
+        if new.keyboard_layouts != old.keyboard_layouts {
+            self.form.set_keyboard_layouts(new.keyboard_layouts.clone());
+            self.greeter_client
+                .set_keyboard_layout(new.keyboard_layouts.first().cloned());
+        }
+        if new.login_max_attempts != old.login_max_attempts
+            || new.login_delay_secs != old.login_delay_secs
+            || new.login_lockout_secs != old.login_lockout_secs
+        {
+            self.greeter_client.throttle_mut().reconfigure(
+                new.login_max_attempts,
+                Duration::from_secs(new.login_delay_secs),
+                Duration::from_secs(new.login_lockout_secs),
+            );
+        }
+        if new.session != old.session
+            || new.username != old.username
+            || new.password != old.password
+        {
+            self.greeter_client.reconfigure(
+                new.session.clone(),
+                new.username.clone(),
+                new.password.clone(),
+            );
+            self.form.set_sessions(
+                self.greeter_client.session_names(),
+                self.greeter_client.resolved_session().map(str::to_string),
+            );
+        }
+
+        Task::batch(tasks)
+    }
+
     pub fn subscription(&self) -> Subscription<Message> {
         let mut subscriptions = vec![
             self.key_listener.subscription().map(Message::KeyListener),
             self.form.subscription().map(Message::Form),
             self.api_poller.subscription().map(Message::ApiPoller),
+            self.announcements
+                .subscription()
+                .map(Message::Announcements),
+            self.broadcast.subscription().map(Message::Broadcast),
             self.countdown.subscription().map(Message::Countdown),
+            self.ip_label.subscription().map(Message::IpLabel),
+            self.config_watcher
+                .subscription()
+                .map(Message::ConfigWatcher),
         ];
         if self.config.enable_dbus {
-            subscriptions.push(dbus_service_subscription().map(Message::Dbus));
+            let settings = DbusSettings {
+                bus: self.config.dbus_bus,
+                socket_path: self.config.dbus_socket_path.clone(),
+                name: self.config.dbus_name.clone(),
+                path: self.config.dbus_path.clone(),
+                policy: AccessPolicy::new(
+                    self.config.dbus_allowed_users.clone(),
+                    self.config.dbus_allowed_groups.clone(),
+                ),
+            };
+            subscriptions.push(dbus_service_subscription(settings).map(Message::Dbus));
         }
         Subscription::batch(subscriptions)
     }
@@ -192,12 +575,26 @@ impl Greeter {
     }
 }
 
-pub fn run_greeter(config: Conf) -> Result<()> {
+pub fn run_greeter(config: Conf, cli: Cli) -> Result<()> {
+    let window = if cli.windowed {
+        window::Settings {
+            size: Size::new(1280.0, 720.0),
+            ..Default::default()
+        }
+    } else {
+        window::Settings {
+            fullscreen: true,
+            decorations: false,
+            ..Default::default()
+        }
+    };
+
     iced::application(
-        move || Greeter::new(config.clone()),
+        move || Greeter::new(config.clone(), &cli),
         Greeter::update,
         Greeter::view,
     )
+    .window(window)
     .subscription(Greeter::subscription)
     .theme(Greeter::style)
     .run()?;
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use iced::{
+    Alignment, Background, Border, Color, Element, Length, Subscription, Task, time,
+    widget::{column, container, text},
+};
+use serde::Deserialize;
+
+use crate::ui::Message;
+
+/// Announcement published by the organizers through the contest API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Announcement {
+    pub id: String,
+    pub text: String,
+    pub valid_from: Option<DateTime<Local>>,
+    pub valid_until: Option<DateTime<Local>>,
+}
+
+impl Announcement {
+    fn is_active(&self, now: DateTime<Local>) -> bool {
+        self.valid_from.is_none_or(|from| from <= now)
+            && self.valid_until.is_none_or(|until| now < until)
+    }
+}
+
+/// Panel listing the announcements that are currently valid.
+#[derive(Debug, Default)]
+pub struct Announcements {
+    announcements: Vec<Announcement>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AnnouncementsMessage {
+    Set(Vec<Announcement>),
+    /// Re-evaluates the validity windows.
+    Tick,
+}
+
+impl From<AnnouncementsMessage> for Message {
+    fn from(value: AnnouncementsMessage) -> Self {
+        Message::Announcements(value)
+    }
+}
+
+impl Announcements {
+    pub fn view(&self) -> Option<Element<'_, AnnouncementsMessage>> {
+        let now = Local::now();
+        let mut active = self
+            .announcements
+            .iter()
+            .filter(|announcement| announcement.is_active(now))
+            .peekable();
+        active.peek()?;
+
+        let content = column(
+            active.map(|announcement| text(&announcement.text).size(18).color(Color::WHITE).into()),
+        )
+        .spacing(8);
+
+        Some(
+            container(
+                container(content)
+                    .padding(16)
+                    .max_width(480)
+                    .style(panel_style),
+            )
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .align_x(Alignment::Start)
+            .align_y(Alignment::End)
+            .padding(40)
+            .into(),
+        )
+    }
+
+    pub fn update(&mut self, msg: AnnouncementsMessage) -> Task<AnnouncementsMessage> {
+        match msg {
+            AnnouncementsMessage::Set(announcements) => self.announcements = announcements,
+            AnnouncementsMessage::Tick => {}
+        }
+        Task::none()
+    }
+
+    pub fn subscription(&self) -> Subscription<AnnouncementsMessage> {
+        if self
+            .announcements
+            .iter()
+            .any(|a| a.valid_from.is_some() || a.valid_until.is_some())
+        {
+            time::every(Duration::from_secs(1)).map(|_| AnnouncementsMessage::Tick)
+        } else {
+            Subscription::none()
+        }
+    }
+}
+
+fn panel_style(_theme: &iced::Theme) -> container::Style {
+    container::Style {
+        background: Some(Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.6))),
+        border: Border {
+            radius: 12.0.into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -35,6 +35,7 @@ pub struct Background {
 #[derive(Debug, Clone)]
 pub enum BackgroundMessage {
     SetSource(Option<String>),
+    UpdateLabel(Option<String>, Option<String>),
     SetData((Option<iced::widget::image::Handle>, Option<Label>)),
 }
 
@@ -52,23 +53,25 @@ impl Background {
     ) -> (Self, Task<BackgroundMessage>) {
         let task = Task::done(BackgroundMessage::SetSource(source));
 
-        let label = label.map(|t| {
-            let color = color
-                .and_then(|h| Color::from_str(&h).ok())
-                .unwrap_or(Color::WHITE);
-
-            Label { text: t, color }
-        });
-
         (
             Self {
-                label,
+                label: new_label(label, color),
                 ..Default::default()
             },
             task,
         )
     }
 
+    /// Status of the wallpaper as exposed over D-Bus.
+    pub fn status(&self) -> &'static str {
+        match self.image_status {
+            ImageStatus::Loading => "loading",
+            ImageStatus::Ready => "ready",
+            ImageStatus::Invalid => "invalid",
+            ImageStatus::Empty => "empty",
+        }
+    }
+
     pub fn view(
         &self,
     ) -> (
@@ -147,6 +150,7 @@ impl Background {
                     self.image_status = ImageStatus::Empty;
                 }
             }
+            BackgroundMessage::UpdateLabel(label, color) => self.label = new_label(label, color),
             BackgroundMessage::SetData((handle, label)) => {
                 match handle {
                     Some(handle) => {
@@ -167,6 +171,16 @@ impl Background {
     }
 }
 
+fn new_label(label: Option<String>, color: Option<String>) -> Option<Label> {
+    label.map(|t| {
+        let color = color
+            .and_then(|h| Color::from_str(&h).ok())
+            .unwrap_or(Color::WHITE);
+
+        Label { text: t, color }
+    })
+}
+
 fn no_background_container<'a>(label: String) -> Element<'a, BackgroundMessage> {
     container(text(label).size(24).color(Color::WHITE))
         .height(Length::Fill)
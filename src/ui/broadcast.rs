@@ -0,0 +1,150 @@
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, Local};
+use iced::{
+    Alignment, Background, Border, Color, Element, Font, Length, Subscription, Task,
+    font::Weight,
+    time,
+    widget::{column, container, text},
+};
+use serde::Deserialize;
+
+use crate::ui::Message;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("unknown severity: {s}")),
+        }
+    }
+}
+
+/// Announcement shown in the message overlay.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Notice {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// The notice is dismissed automatically at this time.
+    pub expires_at: Option<DateTime<Local>>,
+}
+
+/// Message overlay for announcements from the floor managers.
+#[derive(Debug, Default)]
+pub struct Broadcast {
+    notice: Option<Notice>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BroadcastMessage {
+    Show(Notice),
+    Dismiss,
+    Tick,
+}
+
+impl From<BroadcastMessage> for Message {
+    fn from(value: BroadcastMessage) -> Self {
+        Message::Broadcast(value)
+    }
+}
+
+impl Broadcast {
+    pub fn view(&self) -> Option<Element<'_, BroadcastMessage>> {
+        let notice = self.notice.as_ref()?;
+
+        let mut content = column![].spacing(10).align_x(Alignment::Center);
+        if !notice.title.is_empty() {
+            content = content.push(text(&notice.title).size(32).color(Color::WHITE).font(Font {
+                weight: Weight::Bold,
+                ..Default::default()
+            }));
+        }
+        if !notice.body.is_empty() {
+            content = content.push(text(&notice.body).size(24).color(Color::WHITE));
+        }
+
+        let severity = notice.severity;
+        Some(
+            container(
+                container(content)
+                    .padding(20)
+                    .max_width(800)
+                    .style(move |_| notice_style(severity)),
+            )
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .align_x(Alignment::Center)
+            .align_y(Alignment::Start)
+            .padding(40)
+            .into(),
+        )
+    }
+
+    pub fn update(&mut self, msg: BroadcastMessage) -> Task<BroadcastMessage> {
+        match msg {
+            BroadcastMessage::Show(notice) => {
+                if !is_expired(&notice) {
+                    self.notice = Some(notice)
+                }
+            }
+            BroadcastMessage::Dismiss => self.notice = None,
+            BroadcastMessage::Tick => {
+                if self.notice.as_ref().is_some_and(is_expired) {
+                    self.notice = None;
+                }
+            }
+        }
+        Task::none()
+    }
+
+    pub fn subscription(&self) -> Subscription<BroadcastMessage> {
+        if self
+            .notice
+            .as_ref()
+            .is_some_and(|notice| notice.expires_at.is_some())
+        {
+            time::every(Duration::from_secs(1)).map(|_| BroadcastMessage::Tick)
+        } else {
+            Subscription::none()
+        }
+    }
+}
+
+fn is_expired(notice: &Notice) -> bool {
+    notice
+        .expires_at
+        .is_some_and(|expires_at| Local::now() >= expires_at)
+}
+
+fn notice_style(severity: Severity) -> container::Style {
+    let background = match severity {
+        Severity::Info => Color::from_rgba(0.0, 0.0, 0.0, 0.7),
+        Severity::Warning => Color::from_rgba(0.8, 0.5, 0.0, 0.85),
+        Severity::Critical => Color::from_rgba(0.75, 0.1, 0.1, 0.85),
+    };
+    container::Style {
+        background: Some(Background::Color(background)),
+        border: Border {
+            radius: 12.0.into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -12,11 +12,13 @@ use crate::ui::Message;
 pub struct Countdown {
     start_time: Option<DateTime<Local>>,
     now: DateTime<Local>,
+    started: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum CountdownMessage {
     SetStartTime(DateTime<Local>),
+    ClearStartTime,
     Tick,
     Start,
 }
@@ -30,6 +32,20 @@ impl From<CountdownMessage> for Message {
 pub type IndicatorBuilder<'a, Message> = Box<dyn Fn(bool) -> Element<'a, Message> + 'a>;
 
 impl Countdown {
+    pub fn start_time(&self) -> Option<DateTime<Local>> {
+        self.start_time
+    }
+
+    /// State of the countdown as exposed over D-Bus.
+    pub fn state(&self) -> &'static str {
+        match self.start_time {
+            Some(start_time) if (start_time - self.now).num_milliseconds() <= 10000 => "final",
+            Some(_) => "scheduled",
+            None if self.started => "started",
+            None => "idle",
+        }
+    }
+
     pub fn view<'a>(
         &'a self,
     ) -> (
@@ -106,13 +122,18 @@ impl Countdown {
 
     pub fn update(&mut self, msg: CountdownMessage) -> Task<CountdownMessage> {
         match msg {
-            CountdownMessage::SetStartTime(date_time) => self.start_time = Some(date_time),
+            CountdownMessage::SetStartTime(date_time) => {
+                self.start_time = Some(date_time);
+                self.started = false;
+            }
+            CountdownMessage::ClearStartTime => self.start_time = None,
             CountdownMessage::Tick => {
                 self.now = Local::now();
                 if let Some(start_time) = self.start_time
                     && self.now >= start_time
                 {
                     self.start_time = None;
+                    self.started = true;
                     return Task::done(CountdownMessage::Start);
                 }
             }
@@ -0,0 +1,61 @@
+use iced::{
+    Alignment, Background, Border, Color, Element, Font, Length,
+    widget::{column, container, row, text},
+};
+
+/// Overlay listing machine and greeter details for the floor managers.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    visible: bool,
+}
+
+impl Diagnostics {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// `rows` is only called while the overlay is shown.
+    pub fn view<'a, M: 'a>(
+        &self,
+        rows: impl FnOnce() -> Vec<(&'static str, String)>,
+    ) -> Option<Element<'a, M>> {
+        if !self.visible {
+            return None;
+        }
+
+        let content = column(rows().into_iter().map(|(name, value)| {
+            row![
+                text(name)
+                    .size(16)
+                    .width(160)
+                    .color(Color::from_rgb(0.7, 0.7, 0.7)),
+                text(value)
+                    .size(16)
+                    .color(Color::WHITE)
+                    .font(Font::MONOSPACE),
+            ]
+            .into()
+        }))
+        .spacing(6);
+
+        Some(
+            container(container(content).padding(20).style(panel_style))
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .align_x(Alignment::Center)
+                .align_y(Alignment::Center)
+                .into(),
+        )
+    }
+}
+
+fn panel_style(_theme: &iced::Theme) -> container::Style {
+    container::Style {
+        background: Some(Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.85))),
+        border: Border {
+            radius: 12.0.into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -1,38 +1,95 @@
+use std::env;
 use std::ops::Not;
+use std::time::{Duration, Instant};
 
+use iced::advanced::widget::{operate, operation::focusable::unfocus};
 use iced::keyboard::key::Named;
 use iced::widget::operation::focus;
-use iced::widget::{Id, button, column, container, text, text_input};
+use iced::widget::{Id, button, column, container, pick_list, row, text, text_input};
 use iced::{
     Background, Border, Color, Event, Shadow, Subscription, Task, Theme, Vector, event, keyboard,
+    time,
 };
 use iced::{Element, Length};
 
+use log::info;
+
 use crate::ui::Message;
 
+/// Controls of the form in focus order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Username,
+    Password,
+    Session,
+    KeyboardLayout,
+    Login,
+}
+
 #[derive(Debug)]
 pub struct Form {
     // form state
     username: String,
     username_id: Id,
+    /// Username filled in whenever the form is shown or cleared.
+    default_username: String,
+    username_locked: bool,
+    /// When not empty, the username is picked from this list.
+    usernames: Vec<String>,
     password: String,
     password_id: Id,
+    password_visible: bool,
+    caps_lock: bool,
+    /// Sessions to choose from, empty when there is nothing to choose.
+    sessions: Vec<String>,
+    session: Option<String>,
+    keyboard_layouts: Vec<String>,
+    keyboard_layout: Option<String>,
+    /// Layout the compositor uses for typing into the form, from `XKB_DEFAULT_LAYOUT`.
+    greeter_keyboard_layout: String,
+    focused: Field,
     error: Option<String>,
 
     visible: bool,
     is_logging_in: bool,
+
+    /// The form is hidden and cleared after this long without input. Zero disables it.
+    inactivity_timeout: Duration,
+    last_activity: Instant,
+    /// Logins are refused until this time after failed attempts.
+    blocked_until: Option<Instant>,
 }
 
 #[derive(Debug, Clone)]
 pub enum FormMessage {
     UsernameChanged(String),
     PasswordChanged(String),
+    SessionSelected(String),
+    KeyboardLayoutSelected(String),
     Login,
-    LoginWithCredentials(String, String),
+    LoginWithCredentials {
+        username: String,
+        password: String,
+        session: Option<String>,
+        keyboard_layout: Option<String>,
+    },
     ToggleVisible,
-    FocusUsername,
-    FocusPassword,
+    FocusNext,
+    FocusPrevious,
+    /// Arrow keys move the selection of the focused picker.
+    SelectNext,
+    SelectPrevious,
+    /// Enter moves from the username to the next field and submits everywhere else.
+    Submit,
+    Hide,
+    TogglePasswordVisible,
+    /// Caps Lock state derived from a typed letter and the shift modifier.
+    SetCapsLock(bool),
+    CapsLockPressed,
     SetError(String),
+    BlockUntil(Instant),
+    Activity,
+    Tick,
 }
 
 impl From<FormMessage> for Message {
@@ -42,52 +99,294 @@ impl From<FormMessage> for Message {
 }
 
 impl Form {
-    pub fn new() -> Self {
+    pub fn new(inactivity_timeout: Duration) -> Self {
         Self {
             username: Default::default(),
             password: Default::default(),
             username_id: Id::new("username"),
+            default_username: Default::default(),
+            username_locked: false,
+            usernames: Default::default(),
             password_id: Id::new("password"),
+            password_visible: false,
+            caps_lock: false,
+            sessions: Default::default(),
+            session: None,
+            keyboard_layouts: Default::default(),
+            keyboard_layout: None,
+            greeter_keyboard_layout: env::var("XKB_DEFAULT_LAYOUT")
+                .ok()
+                .filter(|layout| !layout.is_empty())
+                .unwrap_or_else(|| "us".to_string()),
+            focused: Field::Username,
             error: Default::default(),
 
             visible: false,
             is_logging_in: false,
+
+            inactivity_timeout,
+            last_activity: Instant::now(),
+            blocked_until: None,
+        }
+    }
+
+    pub fn set_inactivity_timeout(&mut self, inactivity_timeout: Duration) {
+        self.inactivity_timeout = inactivity_timeout;
+    }
+
+    /// Prefills the username, optionally read-only, or restricts it to `usernames`.
+    pub fn set_usernames(
+        &mut self,
+        default_username: Option<String>,
+        locked: bool,
+        usernames: Vec<String>,
+    ) {
+        self.default_username = default_username.unwrap_or_default();
+        self.username_locked = locked && !self.default_username.is_empty();
+        self.usernames = usernames;
+        self.username = self.default_username.clone();
+    }
+
+    /// Offers a session selector when there is more than one session.
+    pub fn set_sessions(&mut self, sessions: Vec<String>, selected: Option<String>) {
+        self.sessions = if sessions.len() > 1 { sessions } else { vec![] };
+        self.session = selected;
+    }
+
+    /// Offers the layouts in a selector, starting with the first one.
+    pub fn set_keyboard_layouts(&mut self, keyboard_layouts: Vec<String>) {
+        self.keyboard_layout = keyboard_layouts.first().cloned();
+        self.keyboard_layouts = keyboard_layouts;
+    }
+
+    /// Fields that can currently be focused, in order.
+    fn fields(&self) -> Vec<Field> {
+        let mut fields = vec![];
+        if !self.username_locked {
+            fields.push(Field::Username);
         }
+        fields.push(Field::Password);
+        if !self.sessions.is_empty() {
+            fields.push(Field::Session);
+        }
+        if !self.keyboard_layouts.is_empty() {
+            fields.push(Field::KeyboardLayout);
+        }
+        fields.push(Field::Login);
+        fields
+    }
+
+    fn focus_field(&mut self, field: Field) -> Task<FormMessage> {
+        self.focused = field;
+        match field {
+            // the picker can not take keyboard focus, the text input is focused instead
+            Field::Username if self.usernames.is_empty() => focus(self.username_id.clone()),
+            Field::Password => focus(self.password_id.clone()),
+            _ => operate(unfocus()),
+        }
+    }
+
+    /// Moves the focus `offset` fields forward, wrapping around.
+    fn cycle_focus(&mut self, offset: isize) -> Task<FormMessage> {
+        let fields = self.fields();
+        let current = fields
+            .iter()
+            .position(|field| *field == self.focused)
+            .unwrap_or(0) as isize;
+        let next = (current + offset).rem_euclid(fields.len() as isize) as usize;
+        self.focus_field(fields[next])
+    }
+
+    /// Moves the selection of the focused picker `offset` options, stopping at either end.
+    fn select_offset(&self, offset: isize) -> Task<FormMessage> {
+        let (options, selected, on_select): (_, _, fn(String) -> FormMessage) = match self.focused {
+            Field::Username if !self.usernames.is_empty() => (
+                &self.usernames,
+                Some(&self.username),
+                FormMessage::UsernameChanged,
+            ),
+            Field::Session => (
+                &self.sessions,
+                self.session.as_ref(),
+                FormMessage::SessionSelected,
+            ),
+            Field::KeyboardLayout => (
+                &self.keyboard_layouts,
+                self.keyboard_layout.as_ref(),
+                FormMessage::KeyboardLayoutSelected,
+            ),
+            _ => return Task::none(),
+        };
+        if options.is_empty() {
+            return Task::none();
+        }
+        let next = match options.iter().position(|option| Some(option) == selected) {
+            Some(current) => (current as isize + offset).clamp(0, options.len() as isize - 1),
+            None if offset > 0 => 0,
+            None => options.len() as isize - 1,
+        };
+        match options.get(next as usize) {
+            Some(option) if Some(option) != selected => Task::done(on_select(option.clone())),
+            _ => Task::none(),
+        }
+    }
+
+    /// Hides the form and forgets everything that was entered. Focus is reset the next
+    /// time the form is shown.
+    fn hide(&mut self) {
+        self.visible = false;
+        self.username = self.default_username.clone();
+        self.password.clear();
+        self.password_visible = false;
+        self.error = None;
     }
 
     pub fn view(&self) -> Option<Element<'_, FormMessage>> {
         if !self.visible {
             return None;
         }
-        let mut content = column![
-            text("Username"),
+        let blocked_for = self
+            .blocked_until
+            .map(|until| until.saturating_duration_since(Instant::now()));
+        let username: Element<'_, FormMessage> = if self.username_locked {
+            text_input("", &self.username)
+                .id(self.username_id.clone())
+                .padding(10)
+                .style(input_style)
+                .into()
+        } else if !self.usernames.is_empty() {
+            pick_list(
+                &self.usernames[..],
+                self.usernames.iter().find(|u| **u == self.username),
+                FormMessage::UsernameChanged,
+            )
+            .placeholder("Select username")
+            .width(Length::Fill)
+            .padding(10)
+            .style({
+                let focused = self.focused == Field::Username;
+                move |theme, status| picker_style(theme, status, focused)
+            })
+            .into()
+        } else {
             text_input("Enter username", &self.username)
                 .id(self.username_id.clone())
                 .on_input(FormMessage::UsernameChanged)
                 .padding(10)
-                .style(input_style),
+                .style(input_style)
+                .into()
+        };
+
+        let mut content = column![
+            text("Username"),
+            username,
             text("Password"),
-            text_input("Enter password", &self.password)
-                .id(self.password_id.clone())
-                .on_input(FormMessage::PasswordChanged)
-                .secure(true)
+            row![
+                text_input("Enter password", &self.password)
+                    .id(self.password_id.clone())
+                    .on_input(FormMessage::PasswordChanged)
+                    .secure(!self.password_visible)
+                    .padding(10)
+                    .style(input_style),
+                button(if self.password_visible {
+                    "Hide"
+                } else {
+                    "Show"
+                })
+                .on_press(FormMessage::TogglePasswordVisible)
+                .padding(10)
+                .style(toggle_style),
+            ]
+            .spacing(6),
+        ]
+        .spacing(10)
+        .padding(20);
+
+        if !self.sessions.is_empty() {
+            let focused = self.focused == Field::Session;
+            content = content.push(text("Session"));
+            content = content.push(
+                pick_list(
+                    &self.sessions[..],
+                    self.session.as_ref(),
+                    FormMessage::SessionSelected,
+                )
+                .width(Length::Fill)
                 .padding(10)
-                .style(input_style),
+                .style(move |theme, status| picker_style(theme, status, focused)),
+            );
+        }
+
+        if !self.keyboard_layouts.is_empty() {
+            let focused = self.focused == Field::KeyboardLayout;
+            content = content.push(text("Session keyboard layout"));
+            content = content.push(
+                pick_list(
+                    &self.keyboard_layouts[..],
+                    self.keyboard_layout.as_ref(),
+                    FormMessage::KeyboardLayoutSelected,
+                )
+                .width(Length::Fill)
+                .padding(10)
+                .style(move |theme, status| picker_style(theme, status, focused)),
+            );
+            // the picked layout only applies to the session, not to typing into this form
+            let differs = self
+                .keyboard_layout
+                .as_ref()
+                .is_some_and(|layout| *layout != self.greeter_keyboard_layout);
+            content = content.push(
+                text(format!(
+                    "This form is typed with the {} layout",
+                    self.greeter_keyboard_layout
+                ))
+                .size(14)
+                .color(if differs {
+                    Color::from_rgb(1.0, 0.7, 0.2)
+                } else {
+                    Color::from_rgb(0.7, 0.7, 0.7)
+                }),
+            );
+        }
+
+        if self.caps_lock {
+            content = content.push(
+                text("Caps Lock is on")
+                    .size(14)
+                    .color(Color::from_rgb(1.0, 0.7, 0.2)),
+            );
+        }
+
+        content = content.push(
             button(if !self.is_logging_in {
                 "Login"
             } else {
                 "Logging in..."
             })
-            .on_press_maybe(self.is_logging_in.not().then_some(FormMessage::Login))
+            .on_press_maybe(
+                (self.is_logging_in || blocked_for.is_some())
+                    .not()
+                    .then_some(FormMessage::Login),
+            )
             .padding(10)
-            .style(button_style)
-        ]
-        .spacing(10)
-        .padding(20);
+            .style({
+                let focused = self.focused == Field::Login;
+                move |theme, status| button_style(theme, status, focused)
+            }),
+        );
 
         if let Some(error) = &self.error {
             content = content.push(text(error).color(Color::from_rgb(1.0, 0.4, 0.4)));
         }
+        if let Some(blocked_for) = blocked_for {
+            content = content.push(
+                text(format!(
+                    "Too many failed attempts, try again in {}s",
+                    blocked_for.as_secs() + 1
+                ))
+                .color(Color::from_rgb(1.0, 0.7, 0.2)),
+            );
+        }
 
         Some(
             container(
@@ -103,64 +402,175 @@ impl Form {
 
     pub fn update(&mut self, msg: FormMessage) -> Task<FormMessage> {
         match msg {
-            FormMessage::UsernameChanged(username) => self.username = username,
-            FormMessage::PasswordChanged(password) => self.password = password,
+            FormMessage::UsernameChanged(username) => {
+                self.username = username;
+                self.focused = Field::Username;
+            }
+            FormMessage::PasswordChanged(password) => {
+                self.password = password;
+                self.focused = Field::Password;
+            }
+            FormMessage::SessionSelected(session) => {
+                self.session = Some(session);
+                self.focused = Field::Session;
+            }
+            FormMessage::KeyboardLayoutSelected(keyboard_layout) => {
+                info!("keyboard layout {keyboard_layout} selected for the session");
+                self.keyboard_layout = Some(keyboard_layout);
+                self.focused = Field::KeyboardLayout;
+            }
             FormMessage::Login => {
+                if self.is_logging_in || self.blocked_until.is_some() {
+                    return Task::none();
+                }
                 if self.username.is_empty() {
                     self.error = Some(String::from("Username can not be empty"));
                     return Task::none();
                 }
+                if !self.usernames.is_empty() && !self.usernames.contains(&self.username) {
+                    self.error = Some(String::from("Username is not allowed"));
+                    return Task::none();
+                }
                 if self.password.is_empty() {
                     self.error = Some(String::from("Password can not be empty"));
                     return Task::none();
                 }
                 self.error = None;
                 self.is_logging_in = true;
-                return Task::done(FormMessage::LoginWithCredentials(
-                    self.username.clone(),
-                    self.password.clone(),
-                ));
+                return Task::done(FormMessage::LoginWithCredentials {
+                    username: self.username.clone(),
+                    password: self.password.clone(),
+                    session: self.session.clone(),
+                    keyboard_layout: self.keyboard_layout.clone(),
+                });
             }
             FormMessage::ToggleVisible => {
                 self.visible = !self.visible;
                 if self.visible {
-                    return focus(self.username_id.clone());
+                    self.last_activity = Instant::now();
+                    if self.username.is_empty() && !self.username_locked {
+                        return self.focus_field(Field::Username);
+                    }
+                    return self.focus_field(Field::Password);
+                }
+            }
+            FormMessage::TogglePasswordVisible => self.password_visible = !self.password_visible,
+            FormMessage::SetCapsLock(caps_lock) => self.caps_lock = caps_lock,
+            FormMessage::CapsLockPressed => self.caps_lock = !self.caps_lock,
+            FormMessage::Activity => self.last_activity = Instant::now(),
+            FormMessage::Tick => {
+                if self
+                    .blocked_until
+                    .is_some_and(|until| until <= Instant::now())
+                {
+                    self.blocked_until = None;
+                }
+                if self.visible
+                    && !self.is_logging_in
+                    && !self.inactivity_timeout.is_zero()
+                    && self.last_activity.elapsed() >= self.inactivity_timeout
+                {
+                    info!("hiding login form after inactivity");
+                    self.hide();
+                }
+            }
+            FormMessage::FocusNext => return self.cycle_focus(1),
+            FormMessage::FocusPrevious => return self.cycle_focus(-1),
+            FormMessage::SelectNext => return self.select_offset(1),
+            FormMessage::SelectPrevious => return self.select_offset(-1),
+            FormMessage::Submit => {
+                if self.focused == Field::Username {
+                    return self.cycle_focus(1);
                 }
+                return Task::done(FormMessage::Login);
             }
-            FormMessage::FocusPassword => return focus(self.password_id.clone()),
-            FormMessage::FocusUsername => return focus(self.username_id.clone()),
+            FormMessage::Hide => self.hide(),
             FormMessage::SetError(error) => {
                 self.error = Some(error);
                 self.is_logging_in = false;
             }
+            FormMessage::BlockUntil(until) => {
+                self.blocked_until = Some(until);
+                self.is_logging_in = false;
+            }
             _ => {}
         }
         Task::none()
     }
 
     pub fn subscription(&self) -> Subscription<FormMessage> {
-        event::listen_with(|event, status, _id| {
-            if status == event::Status::Captured {
-                return None;
-            }
-
-            if let Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) = event {
-                match key {
-                    keyboard::Key::Named(Named::Tab) => {
-                        if modifiers.shift() {
-                            Some(FormMessage::FocusUsername)
-                        } else {
-                            Some(FormMessage::FocusPassword)
-                        }
-                    }
-                    keyboard::Key::Named(Named::Enter) => Some(FormMessage::Login),
-                    _ => None,
+        let mut subscriptions = vec![];
+        if self.visible {
+            subscriptions.push(key_subscription());
+            subscriptions.push(event::listen_with(|event, _status, _id| {
+                caps_lock_message(event)
+            }));
+        }
+        if self.visible && (!self.inactivity_timeout.is_zero() || self.blocked_until.is_some()) {
+            subscriptions.push(time::every(Duration::from_secs(1)).map(|_| FormMessage::Tick));
+        }
+        if self.visible && !self.inactivity_timeout.is_zero() {
+            subscriptions.push(event::listen_with(|event, _status, _id| match event {
+                Event::Keyboard(_) | Event::Mouse(_) | Event::Touch(_) => {
+                    Some(FormMessage::Activity)
                 }
-            } else {
-                None
+                _ => None,
+            }));
+        }
+        Subscription::batch(subscriptions)
+    }
+}
+
+fn key_subscription() -> Subscription<FormMessage> {
+    event::listen_with(|event, status, _id| {
+        let Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) = event else {
+            return None;
+        };
+        // text inputs capture Escape to drop their focus, it still closes the form
+        if key == keyboard::Key::Named(Named::Escape) {
+            return Some(FormMessage::Hide);
+        }
+        if status == event::Status::Captured {
+            return None;
+        }
+        match key {
+            keyboard::Key::Named(Named::Tab) if modifiers.shift() => {
+                Some(FormMessage::FocusPrevious)
             }
-        })
+            keyboard::Key::Named(Named::Tab) => Some(FormMessage::FocusNext),
+            keyboard::Key::Named(Named::ArrowDown) => Some(FormMessage::SelectNext),
+            keyboard::Key::Named(Named::ArrowUp) => Some(FormMessage::SelectPrevious),
+            keyboard::Key::Named(Named::Enter) => Some(FormMessage::Submit),
+            _ => None,
+        }
+    })
+}
+
+/// Derives the Caps Lock state from key presses, including the ones captured by the text
+/// inputs. Modifiers do not report Caps Lock, so a letter that is uppercase without shift
+/// (or lowercase with shift) means it is on.
+fn caps_lock_message(event: Event) -> Option<FormMessage> {
+    let Event::Keyboard(keyboard::Event::KeyPressed {
+        key,
+        modifiers,
+        text,
+        ..
+    }) = event
+    else {
+        return None;
+    };
+    if key == keyboard::Key::Named(Named::CapsLock) {
+        return Some(FormMessage::CapsLockPressed);
     }
+
+    let mut chars = text.as_deref()?.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() || c.is_uppercase() == c.is_lowercase() {
+        return None;
+    }
+    Some(FormMessage::SetCapsLock(
+        c.is_uppercase() != modifiers.shift(),
+    ))
 }
 
 fn input_style(theme: &Theme, status: text_input::Status) -> text_input::Style {
@@ -169,9 +579,29 @@ fn input_style(theme: &Theme, status: text_input::Status) -> text_input::Style {
     style
 }
 
-fn button_style(theme: &Theme, status: button::Status) -> button::Style {
+fn button_style(theme: &Theme, status: button::Status, focused: bool) -> button::Style {
     let mut style = button::primary(theme, status);
     style.border.radius = 8.0.into();
+    if focused {
+        style.border.width = 2.0;
+        style.border.color = theme.extended_palette().background.base.text;
+    }
+    style
+}
+
+fn picker_style(theme: &Theme, status: pick_list::Status, focused: bool) -> pick_list::Style {
+    let mut style = pick_list::default(theme, status);
+    style.border.radius = 8.0.into();
+    if focused {
+        style.border.width = 2.0;
+        style.border.color = theme.extended_palette().primary.strong.color;
+    }
+    style
+}
+
+fn toggle_style(theme: &Theme, status: button::Status) -> button::Style {
+    let mut style = button::secondary(theme, status);
+    style.border.radius = 8.0.into();
     style
 }
 
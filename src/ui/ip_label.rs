@@ -1,9 +1,11 @@
+use std::{net::IpAddr, time::Duration};
+
 use iced::{
-    Alignment, Color, Element, Length, Task,
+    Alignment, Color, Element, Length, Subscription, Task, time,
     widget::{container, text},
 };
 use local_ip_address::local_ip;
-use log::error;
+use log::{debug, error, info};
 
 #[derive(Debug)]
 pub struct IpLabel {
@@ -12,7 +14,8 @@ pub struct IpLabel {
 
 #[derive(Debug, Clone)]
 pub enum IpLabelMessage {
-    SetIp(String),
+    Refresh,
+    SetIp(Option<IpAddr>),
 }
 
 impl IpLabel {
@@ -37,18 +40,37 @@ impl IpLabel {
 
     pub fn update(&mut self, msg: IpLabelMessage) -> Task<IpLabelMessage> {
         match msg {
-            IpLabelMessage::SetIp(ip_label) => self.ip_label = ip_label,
+            IpLabelMessage::Refresh => {
+                return Task::perform(get_ip_async(), IpLabelMessage::SetIp);
+            }
+            IpLabelMessage::SetIp(ip) => {
+                let ip_label = ip
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_else(|| "No ip found".to_string());
+                // refreshed every 30s, only log when the address changes
+                if ip_label != self.ip_label {
+                    match ip {
+                        Some(ip) => info!("ip address is {ip}"),
+                        None => error!("failed to get ip address"),
+                    }
+                }
+                self.ip_label = ip_label;
+            }
         };
         Task::none()
     }
+
+    pub fn subscription(&self) -> Subscription<IpLabelMessage> {
+        time::every(Duration::from_secs(30)).map(|_| IpLabelMessage::Refresh)
+    }
 }
 
-async fn get_ip_async() -> String {
+async fn get_ip_async() -> Option<IpAddr> {
     match local_ip() {
-        Ok(ip) => ip.to_string(),
+        Ok(ip) => Some(ip),
         Err(err) => {
-            error!("failed to get ip address: {:?}", err);
-            "No ip found".to_string()
+            debug!("failed to get ip address: {:?}", err);
+            None
         }
     }
 }
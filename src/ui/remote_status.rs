@@ -0,0 +1,50 @@
+use iced::{
+    Alignment, Color, Element, Length,
+    widget::{container, text, tooltip},
+};
+
+/// Small indicator shown while the D-Bus remote control is unavailable.
+#[derive(Debug, Default)]
+pub struct RemoteStatus {
+    error: Option<String>,
+}
+
+impl RemoteStatus {
+    pub fn set_available(&mut self) {
+        self.error = None;
+    }
+
+    pub fn set_unavailable(&mut self, reason: String) {
+        self.error = Some(reason);
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// The reason is only shown as a tooltip when `show_reason` is set.
+    pub fn view<'a, M: 'a>(&'a self, show_reason: bool) -> Option<Element<'a, M>> {
+        let error = self.error.as_ref()?;
+
+        let mut element: Element<'a, M> = container(
+            text("Remote control unavailable")
+                .size(14)
+                .color(Color::from_rgb(1.0, 0.7, 0.2)),
+        )
+        .padding(10)
+        .into();
+
+        if show_reason {
+            element = tooltip(element, text(error), tooltip::Position::Top).into();
+        }
+
+        Some(
+            container(element)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .align_x(Alignment::End)
+                .align_y(Alignment::End)
+                .into(),
+        )
+    }
+}
@@ -25,7 +25,14 @@ class CountdownHandler(BaseHTTPRequestHandler):
         rfc3339 = dt.strftime("%Y-%m-%dT%H:%M:%SZ")
 
         response = {
-            "start_time": rfc3339
+            "start_time": rfc3339,
+            "announcements": [
+                {
+                    "id": "welcome",
+                    "text": "Welcome! Problems are handed out at the start.",
+                    "valid_until": rfc3339,
+                }
+            ],
         }
 
         self.send_response(200)